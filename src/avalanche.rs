@@ -0,0 +1,496 @@
+/* Лавинный эффект: насколько меняется шифртекст при замене одной буквы ключа,
+ * одного отверстия решётки или одной буквы открытого текста */
+
+use crate::error::CipherError;
+use crate::grille_key::{grille_key_hints, GrilleKeyEditor};
+use crate::i18n::{tr, trf};
+use crate::language_model::LATIN;
+use crate::notifications::Notifications;
+use crate::rotating_grille::*;
+use crate::vigener_progressive::*;
+use eframe::egui;
+use egui::plot::{Bar, BarChart, HLine, Legend, Plot};
+use im_native_dialog::ImNativeFileDialog;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/* Изменений открытого текста больше не делается: на графике они уже неразличимы */
+const MAX_TRIALS: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Experiment {
+    VigenerKey,
+    VigenerPlaintext,
+    GrilleKey,
+    GrillePlaintext,
+}
+
+pub const EXPERIMENTS: [Experiment; 4] = [
+    Experiment::VigenerKey,
+    Experiment::VigenerPlaintext,
+    Experiment::GrilleKey,
+    Experiment::GrillePlaintext,
+];
+
+impl Experiment {
+    pub fn title(self) -> &'static str {
+        match self {
+            Experiment::VigenerKey => tr("Виженер: буква ключа"),
+            Experiment::VigenerPlaintext => tr("Виженер: буква открытого текста"),
+            Experiment::GrilleKey => tr("Решётка: отверстие ключа"),
+            Experiment::GrillePlaintext => tr("Решётка: буква открытого текста"),
+        }
+    }
+
+    /* Доля изменившихся букв у случайной подстановки: буква совпадает с вероятностью
+     * 1/33. Решётка лишь переставляет буквы, и такой ориентир к ней неприменим */
+    pub fn random_baseline(self) -> Option<f64> {
+        match self {
+            Experiment::VigenerKey | Experiment::VigenerPlaintext => {
+                Some(1.0 - 1.0 / ALPHABET.len() as f64)
+            }
+            Experiment::GrilleKey | Experiment::GrillePlaintext => None,
+        }
+    }
+}
+
+/* Одно изменение и сколько букв шифртекста после него стали другими */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trial {
+    pub change: String,
+    pub differing: usize,
+    pub total: usize,
+}
+
+impl Trial {
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.differing as f64 / self.total as f64
+        }
+    }
+}
+
+/* Число несовпадающих позиций; буквы сверх длины более короткого текста тоже считаются */
+pub fn hamming(a: &str, b: &str) -> (usize, usize) {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let differing = a.iter().zip(&b).filter(|(x, y)| x != y).count();
+    let total = a.len().max(b.len());
+    (differing + total - a.len().min(b.len()), total)
+}
+
+fn trial(change: String, reference: &str, changed: &str) -> Trial {
+    let (differing, total) = hamming(reference, changed);
+    Trial {
+        change,
+        differing,
+        total,
+    }
+}
+
+/* Следующая буква того же алфавита, по кругу */
+fn next_letter(c: char) -> char {
+    for alphabet in [&ALPHABET[..], &LATIN[..]] {
+        if let Some(i) = alphabet.iter().position(|&a| a == c) {
+            return alphabet[(i + 1) % alphabet.len()];
+        }
+    }
+    c
+}
+
+fn replace_at(letters: &[char], i: usize) -> (String, char) {
+    let mut changed = letters.to_vec();
+    changed[i] = next_letter(letters[i]);
+    let to = changed[i];
+    (changed.into_iter().collect(), to)
+}
+
+pub fn vigener_key_trials(
+    key: &str,
+    alphabet: Alphabet,
+    plaintext: &str,
+) -> Result<Vec<Trial>, CipherError> {
+    let plaintext: String = filter_russian(plaintext.chars()).collect();
+    let encrypt = |key: &str| -> Result<String, CipherError> {
+        Ok(VigenerProgressive::new(key)?
+            .with_alphabet(alphabet)
+            .encrypt(&plaintext))
+    };
+    let reference = encrypt(key)?;
+    let letters: Vec<char> = filter_russian(key.chars()).collect();
+    (0..letters.len())
+        .map(|i| {
+            let (changed, to) = replace_at(&letters, i);
+            let change = trf("буква {}: {} → {}", &[&(i + 1), &letters[i], &to]);
+            Ok(trial(change, &reference, &encrypt(&changed)?))
+        })
+        .collect()
+}
+
+pub fn vigener_plaintext_trials(
+    key: &str,
+    alphabet: Alphabet,
+    plaintext: &str,
+) -> Result<Vec<Trial>, CipherError> {
+    let vig = VigenerProgressive::new(key)?.with_alphabet(alphabet);
+    let letters: Vec<char> = filter_russian(plaintext.chars()).collect();
+    let reference = vig.encrypt(&letters.iter().collect::<String>());
+    Ok((0..letters.len().min(MAX_TRIALS))
+        .map(|i| {
+            let (changed, to) = replace_at(&letters, i);
+            let change = trf("буква {}: {} → {}", &[&(i + 1), &letters[i], &to]);
+            trial(change, &reference, &vig.encrypt(&changed))
+        })
+        .collect())
+}
+
+/* Текст дополняется до целых блоков его же началом, а не случайными буквами,
+ * чтобы отличия вызывало только изменение */
+fn grille_plaintext(text: &str) -> Vec<char> {
+    let letters: Vec<char> = grille_letters(text).collect();
+    let len = letters.len().div_ceil(16) * 16;
+    letters.iter().copied().cycle().take(len).collect()
+}
+
+fn grille_encrypt(grille: &Grille, letters: &str) -> String {
+    grille_letters(&grille.encrypt_text(letters)).collect()
+}
+
+pub fn grille_key_trials(
+    key: &CardboardMatrix,
+    plaintext: &str,
+) -> Result<Vec<Trial>, CipherError> {
+    let grille = Grille::new(*key)?;
+    let text: String = grille_plaintext(plaintext).into_iter().collect();
+    let reference = grille_encrypt(&grille, &text);
+
+    /* Выбранная клетка в каждой орбите; у правильного ключа она ровно одна */
+    let cells = |index: usize| orbit(index / 2, index % 2);
+    let choices: [usize; 4] = std::array::from_fn(|index| {
+        cells(index)
+            .iter()
+            .position(|&(y, x)| key[y][x])
+            .unwrap_or(0)
+    });
+
+    let mut trials = Vec::new();
+    for index in 0..4 {
+        for choice in (0..4).filter(|&choice| choice != choices[index]) {
+            let mut changed = choices;
+            changed[index] = choice;
+            let (from, to) = (cells(index)[choices[index]], cells(index)[choice]);
+            let change = trf(
+                "орбита {}: ({}, {}) → ({}, {})",
+                &[
+                    &(index + 1),
+                    &(from.0 + 1),
+                    &(from.1 + 1),
+                    &(to.0 + 1),
+                    &(to.1 + 1),
+                ],
+            );
+            let grille = Grille::new(key_from_choices(&changed))?;
+            trials.push(trial(change, &reference, &grille_encrypt(&grille, &text)));
+        }
+    }
+    Ok(trials)
+}
+
+pub fn grille_plaintext_trials(
+    key: &CardboardMatrix,
+    plaintext: &str,
+) -> Result<Vec<Trial>, CipherError> {
+    let grille = Grille::new(*key)?;
+    let letters = grille_plaintext(plaintext);
+    let reference = grille_encrypt(&grille, &letters.iter().collect::<String>());
+    Ok((0..letters.len().min(MAX_TRIALS))
+        .map(|i| {
+            let (changed, to) = replace_at(&letters, i);
+            let change = trf("буква {}: {} → {}", &[&(i + 1), &letters[i], &to]);
+            trial(change, &reference, &grille_encrypt(&grille, &changed))
+        })
+        .collect())
+}
+
+/* Средняя, наименьшая и наибольшая доля изменившихся букв */
+pub fn summary(trials: &[Trial]) -> Option<(f64, f64, f64)> {
+    if trials.is_empty() {
+        return None;
+    }
+    let fractions = trials.iter().map(Trial::fraction);
+    let mean = fractions.clone().sum::<f64>() / trials.len() as f64;
+    let min = fractions.clone().fold(f64::INFINITY, f64::min);
+    let max = fractions.fold(0.0, f64::max);
+    Some((mean, min, max))
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+pub fn to_csv(trials: &[Trial]) -> String {
+    let mut csv = tr("изменение,отличается,всего,доля").to_owned();
+    csv.push('\n');
+    for trial in trials {
+        csv.push_str(&format!(
+            "{},{},{},{:.4}\n",
+            csv_field(&trial.change),
+            trial.differing,
+            trial.total,
+            trial.fraction()
+        ));
+    }
+    csv
+}
+
+/* Вкладка эксперимента: параметры сохраняются вместе с рабочей областью, результаты нет */
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AvalanchePanel {
+    experiment: Experiment,
+    vigener_key: String,
+    alphabet_key: String,
+    #[serde(with = "key_rows")]
+    grille_key: CardboardMatrix,
+    plaintext: String,
+    #[serde(skip)]
+    trials: Vec<Trial>,
+    /* Ориентир для последних результатов, а не для выбранного сейчас эксперимента */
+    #[serde(skip)]
+    baseline: Option<f64>,
+    #[serde(skip)]
+    csv_dialog: ImNativeFileDialog<Option<PathBuf>>,
+}
+
+impl Default for AvalanchePanel {
+    fn default() -> Self {
+        AvalanchePanel {
+            experiment: Experiment::VigenerKey,
+            vigener_key: String::new(),
+            alphabet_key: String::new(),
+            grille_key: key_from_choices(&[0, 1, 2, 3]),
+            plaintext: String::new(),
+            trials: Vec::new(),
+            baseline: None,
+            csv_dialog: Default::default(),
+        }
+    }
+}
+
+impl AvalanchePanel {
+    /* Копия с теми же параметрами, но без результатов */
+    pub fn duplicate(&self) -> Self {
+        AvalanchePanel {
+            experiment: self.experiment,
+            vigener_key: self.vigener_key.clone(),
+            alphabet_key: self.alphabet_key.clone(),
+            grille_key: self.grille_key,
+            plaintext: self.plaintext.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn plaintext_mut(&mut self) -> &mut String {
+        &mut self.plaintext
+    }
+
+    fn run(&self) -> Result<Vec<Trial>, CipherError> {
+        let alphabet = Alphabet::parse(&self.alphabet_key)?;
+        match self.experiment {
+            Experiment::VigenerKey => {
+                vigener_key_trials(&self.vigener_key, alphabet, &self.plaintext)
+            }
+            Experiment::VigenerPlaintext => {
+                vigener_plaintext_trials(&self.vigener_key, alphabet, &self.plaintext)
+            }
+            Experiment::GrilleKey => grille_key_trials(&self.grille_key, &self.plaintext),
+            Experiment::GrillePlaintext => {
+                grille_plaintext_trials(&self.grille_key, &self.plaintext)
+            }
+        }
+    }
+
+    fn poll_csv_dialog(&mut self, notifications: &mut Notifications) {
+        match self.csv_dialog.check() {
+            Some(Ok(Some(path))) => match std::fs::write(&path, to_csv(&self.trials)) {
+                Ok(()) => notifications.info(trf("Результаты сохранены в {}", &[&path.display()])),
+                Err(e) => {
+                    notifications.error(trf("Не удалось записать {}: {}", &[&path.display(), &e]))
+                }
+            },
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, notifications: &mut Notifications) {
+        self.poll_csv_dialog(notifications);
+
+        ui.horizontal(|ui| {
+            ui.label(tr("Что изменяется:"));
+            egui::ComboBox::from_id_source("avalanche_experiment")
+                .selected_text(self.experiment.title())
+                .show_ui(ui, |ui| {
+                    for experiment in EXPERIMENTS {
+                        ui.selectable_value(&mut self.experiment, experiment, experiment.title());
+                    }
+                });
+        });
+
+        match self.experiment {
+            Experiment::VigenerKey | Experiment::VigenerPlaintext => {
+                ui.horizontal(|ui| {
+                    ui.label(tr("Ключ: "));
+                    ui.text_edit_singleline(&mut self.vigener_key);
+                });
+                ui.horizontal(|ui| {
+                    ui.label(tr("Алфавит таблицы: "));
+                    ui.text_edit_singleline(&mut self.alphabet_key);
+                });
+            }
+            Experiment::GrilleKey | Experiment::GrillePlaintext => {
+                ui.horizontal(|ui| {
+                    ui.allocate_ui(egui::vec2(120.0, 120.0), |ui| {
+                        ui.add(GrilleKeyEditor::new(&mut self.grille_key));
+                    });
+                    ui.vertical(|ui| grille_key_hints(ui, &self.grille_key));
+                });
+            }
+        }
+        ui.label(tr("Открытый текст"));
+        ui.add(egui::TextEdit::multiline(&mut self.plaintext).desired_rows(3));
+
+        ui.horizontal(|ui| {
+            if ui.button(tr("Провести эксперимент")).clicked() {
+                match self.run() {
+                    Ok(trials) => {
+                        if trials.is_empty() {
+                            notifications.warning(tr("Нечего изменять: пустой ключ или текст"));
+                        }
+                        self.trials = trials;
+                        self.baseline = self.experiment.random_baseline();
+                    }
+                    Err(e) => notifications.warning(e.to_string()),
+                }
+            }
+            if ui
+                .add_enabled(
+                    !self.trials.is_empty(),
+                    egui::Button::new(tr("Экспорт CSV...")),
+                )
+                .clicked()
+            {
+                if let Err(e) = self.csv_dialog.show_save_single_file(None) {
+                    notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                }
+            }
+        });
+
+        let Some((mean, min, max)) = summary(&self.trials) else {
+            return;
+        };
+        ui.label(trf(
+            "Изменилось букв шифртекста: в среднем {}, от {} до {}",
+            &[
+                &format!("{:.1}%", mean * 100.0),
+                &format!("{:.1}%", min * 100.0),
+                &format!("{:.1}%", max * 100.0),
+            ],
+        ));
+
+        let bars: Vec<Bar> = self
+            .trials
+            .iter()
+            .enumerate()
+            .map(|(i, trial)| Bar::new(i as f64 + 1.0, trial.fraction()).name(&trial.change))
+            .collect();
+        Plot::new("avalanche_plot")
+            .height(200.0)
+            .include_y(0.0)
+            .include_y(1.0)
+            .legend(Legend::default())
+            .show(ui, |plot| {
+                plot.bar_chart(BarChart::new(bars).name(tr("Доля изменившихся букв")));
+                if let Some(random) = self.baseline {
+                    plot.hline(HLine::new(random).name(tr("Случайный шифр")));
+                }
+            });
+
+        egui::ScrollArea::vertical()
+            .id_source("avalanche_table")
+            .show(ui, |ui| {
+                egui::Grid::new("avalanche_grid")
+                    .striped(true)
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        ui.strong(tr("Изменение"));
+                        ui.strong(tr("Отличается букв"));
+                        ui.strong(tr("Доля"));
+                        ui.end_row();
+                        for trial in &self.trials {
+                            ui.label(&trial.change);
+                            ui.label(format!("{} / {}", trial.differing, trial.total));
+                            ui.label(format!("{:.1}%", trial.fraction() * 100.0));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAINTEXT: &str = "Съешь же ещё этих мягких французских булок да выпей чаю";
+
+    #[test]
+    fn vigener_key_letter_changes_only_its_column() {
+        let trials = vigener_key_trials("КЛЮЧ", Alphabet::default(), PLAINTEXT).unwrap();
+        assert_eq!(trials.len(), 4);
+        let letters = filter_russian(PLAINTEXT.chars()).count();
+        for (i, trial) in trials.iter().enumerate() {
+            /* Меняются ровно позиции i, i + 4, i + 8, ... */
+            assert_eq!(trial.differing, (letters - i).div_ceil(4));
+            assert_eq!(trial.total, letters);
+        }
+        let plaintext = vigener_plaintext_trials("КЛЮЧ", Alphabet::default(), PLAINTEXT).unwrap();
+        assert!(plaintext.iter().all(|trial| trial.differing == 1));
+        assert!(vigener_key_trials("", Alphabet::default(), PLAINTEXT).is_err());
+        assert_eq!(Experiment::VigenerKey.random_baseline(), Some(32.0 / 33.0));
+    }
+
+    #[test]
+    fn grille_changes_are_permutations() {
+        let key = key_from_choices(&[0, 1, 2, 3]);
+        let trials = grille_key_trials(&key, PLAINTEXT).unwrap();
+        assert_eq!(trials.len(), 12);
+        assert!(trials.iter().all(|trial| trial.total % 16 == 0));
+        assert!(trials.iter().all(|trial| trial.differing > 0));
+        let plaintext = grille_plaintext_trials(&key, PLAINTEXT).unwrap();
+        assert!(plaintext.iter().all(|trial| trial.differing == 1));
+        assert!(grille_key_trials(&[[false; 4]; 4], PLAINTEXT).is_err());
+        assert_eq!(Experiment::GrilleKey.random_baseline(), None);
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        assert_eq!(hamming("АБВ", "АГВД"), (2, 4));
+        let trials = [Trial {
+            change: "орбита 1: (1, 1) → (1, 4)".to_owned(),
+            differing: 3,
+            total: 16,
+        }];
+        let csv = to_csv(&trials);
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.ends_with("\"орбита 1: (1, 1) → (1, 4)\",3,16,0.1875\n"));
+        let (mean, min, max) = summary(&trials).unwrap();
+        assert_eq!((mean, min, max), (0.1875, 0.1875, 0.1875));
+    }
+}
@@ -0,0 +1,559 @@
+/* Карточки решётки для печати: сама решётка с отверстиями для вырезания и шаблон листа,
+ * в клетках которого стоит номер вписываемой туда буквы блока */
+
+use crate::error::CipherError;
+use crate::i18n::{tr, trf};
+use crate::language_model::LATIN;
+use crate::notifications::Notifications;
+use crate::rotating_grille::*;
+use eframe::egui;
+use im_native_dialog::ImNativeFileDialog;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/* Сторона квадрата 4x4 без полей, в миллиметрах */
+pub const SIZE_RANGE: RangeInclusive<f64> = 20.0..=250.0;
+pub const DEFAULT_SIZE: f64 = 80.0;
+pub const DPI_RANGE: RangeInclusive<u32> = 72..=600;
+pub const DEFAULT_DPI: u32 = 300;
+
+const LINE: f64 = 0.3;
+const GRID_GRAY: u8 = 190;
+const HOLE_GRAY: u8 = 160;
+
+/* Цифры 3x5 для растра: по строке на байт, старший из трёх битов — левый столбец */
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sheet {
+    Card,
+    Template,
+}
+
+impl Sheet {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "card" => Some(Sheet::Card),
+            "template" => Some(Sheet::Template),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+    Svg,
+    Png,
+}
+
+impl ImageFormat {
+    /* По расширению файла; всё, кроме .png, — SVG */
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("png") => ImageFormat::Png,
+            _ => ImageFormat::Svg,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "svg" => Some(ImageFormat::Svg),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+/* Координаты в миллиметрах от левого верхнего угла, цвет — уровень серого (0 — чёрный) */
+#[derive(Clone, Debug, PartialEq)]
+enum Shape {
+    Rect {
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+        fill: Option<u8>,
+        stroke: Option<u8>,
+    },
+    Triangle([(f64, f64); 3]),
+    /* Число с центром в (x, y), повёрнутое на quarter_turns четвертей по часовой стрелке */
+    Number {
+        x: f64,
+        y: f64,
+        height: f64,
+        value: usize,
+        quarter_turns: usize,
+    },
+}
+
+struct Drawing {
+    side: f64,
+    shapes: Vec<Shape>,
+}
+
+/* Номер буквы блока (с 1), которая попадает в каждую клетку при шифровании */
+pub fn letter_order(key: &CardboardMatrix) -> Result<[[usize; 4]; 4], CipherError> {
+    let letters: String = LATIN[..16].iter().collect();
+    let block = Grille::new(*key)?.encrypt(&letters);
+    Ok(block.map(|row| row.map(|c| letters.find(c).map_or(0, |i| i + 1))))
+}
+
+fn drawing(key: &CardboardMatrix, sheet: Sheet, size: f64) -> Result<Drawing, CipherError> {
+    let order = letter_order(key)?;
+    let cell = size / 4.0;
+    let margin = cell * 0.75;
+    let side = size + 2.0 * margin;
+    let cell_rect = |y: usize, x: usize, inset: f64, fill, stroke| Shape::Rect {
+        x: margin + x as f64 * cell + inset,
+        y: margin + y as f64 * cell + inset,
+        w: cell - 2.0 * inset,
+        h: cell - 2.0 * inset,
+        fill,
+        stroke,
+    };
+
+    let mut shapes = vec![
+        Shape::Rect {
+            x: 0.0,
+            y: 0.0,
+            w: side,
+            h: side,
+            fill: Some(255),
+            stroke: Some(0),
+        },
+        /* Метка ориентации: уголок решётки совмещается с уголком шаблона */
+        Shape::Triangle([(0.0, 0.0), (margin * 0.8, 0.0), (0.0, margin * 0.8)]),
+    ];
+    for y in 0..4 {
+        for x in 0..4 {
+            match sheet {
+                Sheet::Card => {
+                    shapes.push(cell_rect(y, x, 0.0, None, Some(GRID_GRAY)));
+                    if key[y][x] {
+                        shapes.push(cell_rect(y, x, cell * 0.1, Some(HOLE_GRAY), Some(0)));
+                    }
+                }
+                Sheet::Template => {
+                    shapes.push(cell_rect(y, x, 0.0, None, Some(0)));
+                    shapes.push(Shape::Number {
+                        x: margin + (x as f64 + 0.2) * cell,
+                        y: margin + (y as f64 + 0.2) * cell,
+                        height: cell * 0.18,
+                        value: order[y][x],
+                        quarter_turns: 0,
+                    });
+                }
+            }
+        }
+    }
+    if sheet == Sheet::Card {
+        /* Решётка поворачивается по часовой стрелке: при повороте k наверху сторона с числом k,
+         * и число читается прямо */
+        let middle = side / 2.0;
+        let edge = margin / 2.0;
+        let sides = [
+            (middle, edge),
+            (edge, middle),
+            (middle, side - edge),
+            (side - edge, middle),
+        ];
+        for (turn, (x, y)) in sides.into_iter().enumerate() {
+            shapes.push(Shape::Number {
+                x,
+                y,
+                height: margin * 0.5,
+                value: turn + 1,
+                quarter_turns: (4 - turn) % 4,
+            });
+        }
+    }
+    Ok(Drawing { side, shapes })
+}
+
+fn svg_color(gray: Option<u8>) -> String {
+    match gray {
+        Some(g) => format!("#{g:02x}{g:02x}{g:02x}"),
+        None => "none".to_owned(),
+    }
+}
+
+/* Размеры SVG заданы в миллиметрах, поэтому при печати без масштабирования сторона
+ * решётки будет ровно size */
+pub fn to_svg(key: &CardboardMatrix, sheet: Sheet, size: f64) -> Result<String, CipherError> {
+    let drawing = drawing(key, sheet, size)?;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{s:.2}mm\" height=\"{s:.2}mm\" \
+         viewBox=\"0 0 {s:.2} {s:.2}\" stroke-width=\"{LINE}\">\n",
+        s = drawing.side,
+    );
+    for shape in &drawing.shapes {
+        let element = match *shape {
+            Shape::Rect {
+                x,
+                y,
+                w,
+                h,
+                fill,
+                stroke,
+            } => format!(
+                "<rect x=\"{x:.2}\" y=\"{y:.2}\" width=\"{w:.2}\" height=\"{h:.2}\" \
+                 fill=\"{}\" stroke=\"{}\"/>",
+                svg_color(fill),
+                svg_color(stroke)
+            ),
+            Shape::Triangle(points) => format!(
+                "<polygon points=\"{}\" fill=\"#000000\"/>",
+                points
+                    .iter()
+                    .map(|(x, y)| format!("{x:.2},{y:.2}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            Shape::Number {
+                x,
+                y,
+                height,
+                value,
+                quarter_turns,
+            } => format!(
+                "<text x=\"{x:.2}\" y=\"{y:.2}\" font-family=\"sans-serif\" \
+                 font-size=\"{:.2}\" text-anchor=\"middle\" dominant-baseline=\"central\" \
+                 transform=\"rotate({} {x:.2} {y:.2})\">{value}</text>",
+                /* Высота цифр — около 0,72 кегля */
+                height / 0.72,
+                quarter_turns * 90
+            ),
+        };
+        svg.push_str(&element);
+        svg.push('\n');
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/* Простейший растеризатор в оттенках серого: прямоугольники, треугольники и цифры */
+struct Canvas {
+    width: usize,
+    pixels: Vec<u8>,
+    /* Пикселей на миллиметр */
+    scale: f64,
+}
+
+impl Canvas {
+    fn new(side: f64, dpi: u32) -> Self {
+        let scale = dpi as f64 / 25.4;
+        let width = ((side * scale).round() as usize).max(1);
+        Canvas {
+            width,
+            pixels: vec![255; width * width],
+            scale,
+        }
+    }
+
+    fn px(&self, mm: f64) -> usize {
+        ((mm * self.scale).round().max(0.0) as usize).min(self.width)
+    }
+
+    fn fill(&mut self, x0: f64, y0: f64, x1: f64, y1: f64, gray: u8) {
+        let (x0, x1) = (self.px(x0), self.px(x1));
+        for y in self.px(y0)..self.px(y1) {
+            self.pixels[y * self.width + x0..y * self.width + x1].fill(gray);
+        }
+    }
+
+    /* Линии не тоньше пикселя, чтобы не пропадали при малом разрешении */
+    fn stroke(&mut self, x: f64, y: f64, w: f64, h: f64, gray: u8) {
+        let t = LINE.max(1.0 / self.scale) / 2.0;
+        self.fill(x - t, y - t, x + w + t, y + t, gray);
+        self.fill(x - t, y + h - t, x + w + t, y + h + t, gray);
+        self.fill(x - t, y - t, x + t, y + h + t, gray);
+        self.fill(x + w - t, y - t, x + w + t, y + h + t, gray);
+    }
+
+    fn triangle(&mut self, points: [(f64, f64); 3]) {
+        let edge = |(ax, ay): (f64, f64), (bx, by): (f64, f64), (px, py): (f64, f64)| {
+            (bx - ax) * (py - ay) - (by - ay) * (px - ax)
+        };
+        /* Проверяются только пиксели описанного прямоугольника; на пикселе левее
+         * округление может отбросить центр, лежащий ровно на стороне */
+        let (xs, ys) = (points.map(|p| p.0), points.map(|p| p.1));
+        let min = |v: [f64; 3]| self.px(v[0].min(v[1]).min(v[2])).saturating_sub(1);
+        let max = |v: [f64; 3]| self.px(v[0].max(v[1]).max(v[2]));
+        let (columns, rows) = (min(xs)..max(xs), min(ys)..max(ys));
+        for y in rows {
+            for x in columns.clone() {
+                let p = ((x as f64 + 0.5) / self.scale, (y as f64 + 0.5) / self.scale);
+                let signs = [
+                    edge(points[0], points[1], p),
+                    edge(points[1], points[2], p),
+                    edge(points[2], points[0], p),
+                ];
+                if signs.iter().all(|&s| s >= 0.0) || signs.iter().all(|&s| s <= 0.0) {
+                    self.pixels[y * self.width + x] = 0;
+                }
+            }
+        }
+    }
+
+    fn number(&mut self, x: f64, y: f64, height: f64, value: usize, quarter_turns: usize) {
+        let unit = height / 5.0;
+        let digits: Vec<usize> = value
+            .to_string()
+            .bytes()
+            .map(|b| (b - b'0') as usize)
+            .collect();
+        let width = (digits.len() * 4 - 1) as f64;
+        for (n, &digit) in digits.iter().enumerate() {
+            for (row, bits) in DIGITS[digit].iter().enumerate() {
+                for column in (0..3).filter(|column| bits & (0b100 >> column) != 0) {
+                    let mut u = (n * 4 + column) as f64 + 0.5 - width / 2.0;
+                    let mut v = row as f64 + 0.5 - 2.5;
+                    for _ in 0..quarter_turns {
+                        (u, v) = (-v, u);
+                    }
+                    self.fill(
+                        x + (u - 0.5) * unit,
+                        y + (v - 0.5) * unit,
+                        x + (u + 0.5) * unit,
+                        y + (v + 0.5) * unit,
+                        0,
+                    );
+                }
+            }
+        }
+    }
+
+    fn to_png(&self, dpi: u32) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width as u32, self.width as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        /* Разрешение в точках на метр, чтобы программы печати знали физический размер */
+        let ppm = (dpi as f64 / 0.0254).round() as u32;
+        let mut phys = Vec::with_capacity(9);
+        phys.extend_from_slice(&ppm.to_be_bytes());
+        phys.extend_from_slice(&ppm.to_be_bytes());
+        phys.push(1);
+        writer.write_chunk(png::chunk::pHYs, &phys)?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(bytes)
+    }
+}
+
+pub fn to_png(
+    key: &CardboardMatrix,
+    sheet: Sheet,
+    size: f64,
+    dpi: u32,
+) -> Result<Vec<u8>, CipherError> {
+    let drawing = drawing(key, sheet, size)?;
+    let mut canvas = Canvas::new(drawing.side, dpi);
+    for shape in &drawing.shapes {
+        match *shape {
+            Shape::Rect {
+                x,
+                y,
+                w,
+                h,
+                fill,
+                stroke,
+            } => {
+                if let Some(gray) = fill {
+                    canvas.fill(x, y, x + w, y + h, gray);
+                }
+                if let Some(gray) = stroke {
+                    canvas.stroke(x, y, w, h, gray);
+                }
+            }
+            Shape::Triangle(points) => canvas.triangle(points),
+            Shape::Number {
+                x,
+                y,
+                height,
+                value,
+                quarter_turns,
+            } => canvas.number(x, y, height, value, quarter_turns),
+        }
+    }
+    Ok(canvas.to_png(dpi)?)
+}
+
+pub fn render(
+    key: &CardboardMatrix,
+    sheet: Sheet,
+    size: f64,
+    format: ImageFormat,
+    dpi: u32,
+) -> Result<Vec<u8>, CipherError> {
+    match format {
+        ImageFormat::Svg => Ok(to_svg(key, sheet, size)?.into_bytes()),
+        ImageFormat::Png => to_png(key, sheet, size, dpi),
+    }
+}
+
+/* Кнопки сохранения решётки и шаблона; формат выбирается по расширению файла */
+pub struct CardPanel {
+    size: f64,
+    dpi: u32,
+    dialog: ImNativeFileDialog<Option<PathBuf>>,
+    pending: Option<(CardboardMatrix, Sheet)>,
+}
+
+impl Default for CardPanel {
+    fn default() -> Self {
+        CardPanel {
+            size: DEFAULT_SIZE,
+            dpi: DEFAULT_DPI,
+            dialog: Default::default(),
+            pending: None,
+        }
+    }
+}
+
+impl CardPanel {
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        key: &CardboardMatrix,
+        notifications: &mut Notifications,
+    ) {
+        match self.dialog.check() {
+            Some(Ok(Some(path))) => {
+                if let Some((key, sheet)) = self.pending.take() {
+                    let format = ImageFormat::from_path(&path);
+                    let saved = render(&key, sheet, self.size, format, self.dpi)
+                        .and_then(|bytes| Ok(std::fs::write(&path, bytes)?));
+                    match saved {
+                        Ok(()) => notifications.info(trf("Сохранено в {}", &[&path.display()])),
+                        Err(e) => notifications
+                            .error(trf("Не удалось записать {}: {}", &[&path.display(), &e])),
+                    }
+                }
+            }
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(tr("Сторона, мм:"));
+            ui.add(
+                egui::DragValue::new(&mut self.size)
+                    .clamp_range(SIZE_RANGE)
+                    .speed(1.0),
+            );
+            ui.label(tr("Точек на дюйм для PNG:"));
+            ui.add(egui::DragValue::new(&mut self.dpi).clamp_range(DPI_RANGE));
+        });
+        ui.horizontal(|ui| {
+            let valid = is_valid_key(key);
+            for (sheet, title) in [
+                (Sheet::Card, tr("Решётка...")),
+                (Sheet::Template, tr("Шаблон...")),
+            ] {
+                if ui
+                    .add_enabled(valid, egui::Button::new(title))
+                    .on_hover_text(tr("Сохранить для печати: .svg — SVG, .png — PNG"))
+                    .on_disabled_hover_text(tr("Сначала составьте правильный ключ"))
+                    .clicked()
+                {
+                    self.pending = Some((*key, sheet));
+                    if let Err(e) = self.dialog.show_save_single_file(None) {
+                        notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_numbers_follow_encryption() {
+        let key = key_from_choices(&[2, 0, 3, 1]);
+        let order = letter_order(&key).unwrap();
+        let mut all: Vec<usize> = order.iter().flatten().copied().collect();
+        all.sort_unstable();
+        assert_eq!(all, (1..=16).collect::<Vec<_>>());
+        /* Первые четыре буквы — в отверстиях ключа по строкам */
+        let holes: Vec<usize> = (0..16)
+            .filter(|i| key[i / 4][i % 4])
+            .map(|i| order[i / 4][i % 4])
+            .collect();
+        assert_eq!(holes, [1, 2, 3, 4]);
+        assert!(letter_order(&[[false; 4]; 4]).is_err());
+    }
+
+    #[test]
+    fn triangle_fills_only_its_area() {
+        /* 10 пикселей на миллиметр */
+        let mut canvas = Canvas::new(10.0, 254);
+        canvas.triangle([(2.0, 2.0), (8.0, 2.0), (2.0, 8.0)]);
+        let black: Vec<(usize, usize)> = (0..canvas.pixels.len())
+            .filter(|&i| canvas.pixels[i] == 0)
+            .map(|i| (i % canvas.width, i / canvas.width))
+            .collect();
+        assert!((1790..=1870).contains(&black.len()), "{}", black.len());
+        assert!(black
+            .iter()
+            .all(|&(x, y)| (20..80).contains(&x) && (20..80).contains(&y) && x + y < 100));
+        /* Вершины за краем холста не выводят за его пределы */
+        canvas.triangle([(-5.0, -5.0), (20.0, 0.0), (0.0, 20.0)]);
+        assert_eq!(canvas.pixels[0], 0);
+    }
+
+    #[test]
+    fn svg_has_physical_size_and_holes() {
+        let key = key_from_choices(&[0, 1, 2, 3]);
+        let svg = to_svg(&key, Sheet::Card, 80.0).unwrap();
+        /* 80 мм решётки и по 15 мм полей */
+        assert!(svg.contains("width=\"110.00mm\""));
+        assert_eq!(svg.matches("fill=\"#a0a0a0\"").count(), 4);
+        assert_eq!(svg.matches("<text").count(), 4);
+        let template = to_svg(&key, Sheet::Template, 80.0).unwrap();
+        assert_eq!(template.matches("<text").count(), 16);
+    }
+
+    #[test]
+    fn png_is_rasterised_at_chosen_resolution() {
+        let key = key_from_choices(&[0, 1, 2, 3]);
+        let bytes = to_png(&key, Sheet::Card, 40.0, 100).unwrap();
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        /* 55 мм при 100 точках на дюйм */
+        assert_eq!(reader.info().width, 217);
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        let at = |x_mm: f64, y_mm: f64| {
+            let scale = 100.0 / 25.4;
+            pixels[(y_mm * scale) as usize * 217 + (x_mm * scale) as usize]
+        };
+        /* Угол с меткой ориентации чёрный, середина отверстия серая, поле белое */
+        assert_eq!(at(0.5, 0.5), 0);
+        let (y, x) = (0..16)
+            .map(|i| (i / 4, i % 4))
+            .find(|&(y, x)| key[y][x])
+            .unwrap();
+        assert_eq!(
+            at(7.5 + 10.0 * x as f64 + 5.0, 7.5 + 10.0 * y as f64 + 5.0),
+            HOLE_GRAY
+        );
+        assert_eq!(at(54.0, 1.0), 255);
+    }
+}
@@ -0,0 +1,928 @@
+/* Командная строка: без аргументов запускается окно, с аргументами — пакетная обработка */
+
+use crate::card::{self, ImageFormat, Sheet};
+use crate::container::{CipherKind, Container, StageKind};
+use crate::exercise::{self, ExerciseCipher, ExerciseConfig, TextSource};
+use crate::grille_attack::search;
+use crate::key_file::KeyFile;
+use crate::language_model::{model, Language};
+use crate::passphrase;
+use crate::pipeline::{Pipeline, Stage};
+use crate::report::{grille_key_line, AttackMethod, AttackResult, Report, ReportFormat};
+use crate::rotating_grille::grille_letters;
+use crate::text_encoding::decode_auto;
+use crate::vigener_attack::{dictionary_attack, parse_wordlist};
+use crate::vigener_progressive::Alphabet;
+use rand::Rng;
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+const USAGE: &str = "\
+Использование:
+  rotating_net                                     запустить окно
+  rotating_net encrypt|decrypt --key-file <ключ.toml> [-i вход] [-o выход]
+  rotating_net pipeline <конвейер.ron> encrypt|decrypt [-i вход] [-o выход]
+  rotating_net seal (--key-file <ключ.toml> | --pipeline <конвейер.ron>) [--mac on|off]
+                    [-i вход] [-o контейнер]
+  rotating_net open (--key-file <ключ.toml> | --pipeline <конвейер.ron>) [-i контейнер] [-o выход]
+  rotating_net key vigener|grille <парольная фраза> [-o ключ.toml]
+  rotating_net report (--key-file <ключ.toml> | --pipeline <конвейер.ron>) [--format md|html]
+                      [--wordlist <слова.txt>] [--search on|off] [-i открытый текст] [-o отчёт]
+  rotating_net card --key-file <ключ.toml> [--sheet card|template] [--size мм] [--dpi N]
+                    [--format svg|png] [-o рисунок]
+  rotating_net exercises vigener|grille -o <каталог> [--count N] [--seed S] [--letters N]
+                    [--key-length N] [--language ru|en] [--text corpus|random]
+                    [--corpus <текст.txt>] [--give-key on|off]
+  rotating_net help                                эта справка
+
+Без -i текст читается со стандартного ввода, без -o пишется в стандартный вывод.
+Контейнер по умолчанию содержит имитовставку: open сообщит о неверном ключе или подмене.
+Отчёт report содержит шифртекст, статистику и, для одиночного ключа, результаты атак:
+--wordlist — перебор словаря для Виженера, --search on — перебор всех ключей решётки.
+Без --format формат выбирается по расширению -o: .html — HTML, иначе Markdown.
+card рисует решётку для вырезания (card) или шаблон с номерами букв (template);
+--size — сторона решётки в миллиметрах (по умолчанию 80), --dpi — разрешение PNG (300).
+Без --format: .png — PNG, иначе SVG.
+exercises создаёт в каталоге задания variant_NN.txt, ключи variant_NN.key.toml и answers.txt;
+без --seed зерно выбирается случайно и печатается, чтобы варианты можно было повторить.";
+
+/* Позиционные аргументы и параметры вида «-x значение» / «--name значение» */
+struct Options {
+    positional: Vec<String>,
+    named: Vec<(String, String)>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options {
+            positional: Vec::new(),
+            named: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg.starts_with('-') && arg.len() > 1 {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("после {arg} ожидается значение"))?;
+                options.named.push((arg.clone(), value.clone()));
+            } else {
+                options.positional.push(arg.clone());
+            }
+        }
+        Ok(options)
+    }
+
+    fn value(&self, names: &[&str]) -> Option<&str> {
+        self.named
+            .iter()
+            .rev()
+            .find(|(name, _)| names.contains(&name.as_str()))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn check_known(&self, known: &[&str]) -> Result<(), String> {
+        match self
+            .named
+            .iter()
+            .find(|(name, _)| !known.contains(&name.as_str()))
+        {
+            Some((name, _)) => Err(format!("неизвестный параметр {name}")),
+            None => Ok(()),
+        }
+    }
+
+    /* Параметр вида on|off */
+    fn switch(&self, name: &str, default: bool) -> Result<bool, String> {
+        match self.value(&[name]) {
+            None => Ok(default),
+            Some("on") => Ok(true),
+            Some("off") => Ok(false),
+            Some(other) => Err(format!("{name}: ожидается on или off, а не {other}")),
+        }
+    }
+
+    fn input(&self) -> Result<String, String> {
+        Ok(decode_auto(&self.input_bytes()?).text)
+    }
+
+    fn input_bytes(&self) -> Result<Vec<u8>, String> {
+        Ok(match self.value(&["-i", "--input"]) {
+            Some(path) => std::fs::read(path).map_err(|e| format!("{path}: {e}"))?,
+            None => {
+                let mut bytes = Vec::new();
+                io::stdin()
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| format!("стандартный ввод: {e}"))?;
+                bytes
+            }
+        })
+    }
+
+    fn output(&self, text: &str) -> Result<(), String> {
+        self.output_bytes(text.as_bytes())
+    }
+
+    fn output_bytes(&self, bytes: &[u8]) -> Result<(), String> {
+        match self.value(&["-o", "--output"]) {
+            Some(path) => std::fs::write(path, bytes).map_err(|e| format!("{path}: {e}")),
+            None => io::stdout()
+                .write_all(bytes)
+                .map_err(|e| format!("стандартный вывод: {e}")),
+        }
+    }
+}
+
+fn pipeline(options: &Options) -> Result<(), String> {
+    options.check_known(&["-i", "--input", "-o", "--output"])?;
+    let [path, direction] = options.positional.as_slice() else {
+        return Err("ожидаются файл конвейера и encrypt или decrypt".to_owned());
+    };
+    let encrypt = match direction.as_str() {
+        "encrypt" => true,
+        "decrypt" => false,
+        other => return Err(format!("неизвестное направление {other}")),
+    };
+    let pipeline = Pipeline::load(Path::new(path)).map_err(|e| format!("{path}: {e}"))?;
+    let input = options.input()?;
+    let output = if encrypt {
+        pipeline.encrypt(&input)
+    } else {
+        pipeline.decrypt(&input)
+    };
+    options.output(&output.map_err(|e| e.to_string())?)
+}
+
+fn with_key_file(options: &Options, encrypt: bool) -> Result<(), String> {
+    options.check_known(&["-k", "--key-file", "-i", "--input", "-o", "--output"])?;
+    if !options.positional.is_empty() {
+        return Err(format!("лишний аргумент {}", options.positional[0]));
+    }
+    let path = options
+        .value(&["-k", "--key-file"])
+        .ok_or("нужен файл ключа: --key-file <ключ.toml>")?;
+    let file = KeyFile::load(Path::new(path)).map_err(|e| format!("{path}: {e}"))?;
+    let input = options.input()?;
+    let output = if encrypt {
+        file.key.encrypt(&input).map_err(|e| e.to_string())?
+    } else {
+        file.key.decrypt(&input).map_err(|e| e.to_string())?
+    };
+    options.output(&output)
+}
+
+/* Файл ключа, выведенного из парольной фразы */
+fn key(options: &Options) -> Result<(), String> {
+    options.check_known(&["-o", "--output"])?;
+    let [cipher, phrase] = options.positional.as_slice() else {
+        return Err("ожидаются шифр (vigener или grille) и парольная фраза".to_owned());
+    };
+    let key = match cipher.as_str() {
+        "vigener" => passphrase::vigener_key(phrase).map(|key| Stage::Vigener {
+            key,
+            alphabet_key: String::new(),
+        }),
+        "grille" => passphrase::grille_key(phrase).map(|key| Stage::Grille { key }),
+        other => return Err(format!("неизвестный шифр {other}")),
+    };
+    let file = KeyFile {
+        comment: "выведен из парольной фразы".to_owned(),
+        key: key.ok_or("пустая парольная фраза")?,
+    };
+    options.output(&file.to_toml())
+}
+
+/* Шифр для контейнера: одиночный ключ из файла ключа или каскад */
+fn container_cipher(options: &Options) -> Result<(CipherKind, Pipeline), String> {
+    options.check_known(&[
+        "-k",
+        "--key-file",
+        "-p",
+        "--pipeline",
+        "--mac",
+        "-i",
+        "--input",
+        "-o",
+        "--output",
+    ])?;
+    load_cipher(options).map(|(cipher, pipeline, _)| (cipher, pipeline))
+}
+
+/* Файл ключа или конвейера из --key-file / --pipeline и комментарий файла ключа
+ * (у конвейера его нет) */
+fn load_cipher(options: &Options) -> Result<(CipherKind, Pipeline, String), String> {
+    if !options.positional.is_empty() {
+        return Err(format!("лишний аргумент {}", options.positional[0]));
+    }
+    match (
+        options.value(&["-k", "--key-file"]),
+        options.value(&["-p", "--pipeline"]),
+    ) {
+        (Some(path), None) => {
+            let file = KeyFile::load(Path::new(path)).map_err(|e| format!("{path}: {e}"))?;
+            let cipher = match StageKind::of(&file.key) {
+                StageKind::Vigener => CipherKind::Vigener,
+                StageKind::Grille => CipherKind::Grille,
+            };
+            let pipeline = Pipeline {
+                stages: vec![file.key],
+            };
+            Ok((cipher, pipeline, file.comment))
+        }
+        (None, Some(path)) => {
+            let pipeline = Pipeline::load(Path::new(path)).map_err(|e| format!("{path}: {e}"))?;
+            Ok((CipherKind::Pipeline, pipeline, String::new()))
+        }
+        _ => Err("нужен ровно один из параметров --key-file и --pipeline".to_owned()),
+    }
+}
+
+fn seal(options: &Options) -> Result<(), String> {
+    let (cipher, pipeline) = container_cipher(options)?;
+    let input = options.input()?;
+    let ciphertext = pipeline.encrypt(&input).map_err(|e| e.to_string())?;
+    let keyed = options.switch("--mac", true)?;
+    let container = Container::seal(cipher, &pipeline.stages, &input, ciphertext, keyed)
+        .map_err(|e| e.to_string())?;
+    options.output_bytes(&container.to_bytes())
+}
+
+fn open(options: &Options) -> Result<(), String> {
+    let (cipher, pipeline) = container_cipher(options)?;
+    let container = Container::from_bytes(&options.input_bytes()?).map_err(|e| e.to_string())?;
+    if container.cipher != cipher {
+        return Err(format!(
+            "контейнер для шифра «{}», а ключ — для «{}»",
+            container.cipher.name(),
+            cipher.name()
+        ));
+    }
+    let plaintext = container
+        .open(&pipeline.stages)
+        .map_err(|e| e.to_string())?;
+    options.output(&plaintext)
+}
+
+/* Атаки по шифртексту одиночного ключа, которые просили параметрами */
+fn report_attacks(
+    options: &Options,
+    stages: &[Stage],
+    ciphertext: &str,
+) -> Result<Vec<AttackResult>, String> {
+    let search_requested = options.switch("--search", false)?;
+    let mut attacks = Vec::new();
+    match stages {
+        [Stage::Vigener { alphabet_key, .. }] => {
+            if let Some(path) = options.value(&["--wordlist"]) {
+                let bytes = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+                let words = parse_wordlist(&decode_auto(&bytes).text);
+                let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+                let hits = dictionary_attack(
+                    ciphertext,
+                    &words,
+                    Alphabet::parse(alphabet_key).map_err(|e| e.to_string())?,
+                    &model(Language::Russian),
+                    5,
+                    threads,
+                    |_| false,
+                );
+                attacks.extend(hits.into_iter().map(|hit| AttackResult {
+                    method: AttackMethod::Dictionary,
+                    key: hit.key,
+                    score: hit.score,
+                    plaintext: hit.plaintext,
+                }));
+            }
+        }
+        [Stage::Grille { .. }] if search_requested => {
+            if grille_letters(ciphertext).count() < 16 {
+                return Err("для перебора нужен хотя бы один полный блок (16 букв)".to_owned());
+            }
+            let model = model(Language::of_text(ciphertext));
+            let found = search(ciphertext, &model, |_, _| false);
+            attacks.extend(found.map(|candidate| AttackResult {
+                method: AttackMethod::KeySearch,
+                key: grille_key_line(&candidate.key),
+                score: candidate.score,
+                plaintext: candidate.plaintext,
+            }));
+        }
+        _ => {
+            if options.value(&["--wordlist"]).is_some() || search_requested {
+                return Err(
+                    "атаки доступны только для одиночного ключа: --wordlist для Виженера, \
+                     --search для решётки"
+                        .to_owned(),
+                );
+            }
+        }
+    }
+    Ok(attacks)
+}
+
+/* Формат из --format, иначе по расширению -o */
+fn report_format(options: &Options) -> Result<ReportFormat, String> {
+    match (
+        options.value(&["--format"]),
+        options.value(&["-o", "--output"]),
+    ) {
+        (Some(name), _) => ReportFormat::parse(name)
+            .ok_or_else(|| format!("--format: ожидается md или html, а не {name}")),
+        (None, Some(path)) => Ok(ReportFormat::from_path(Path::new(path))),
+        (None, None) => Ok(ReportFormat::Markdown),
+    }
+}
+
+fn image_format(options: &Options) -> Result<ImageFormat, String> {
+    match (
+        options.value(&["--format"]),
+        options.value(&["-o", "--output"]),
+    ) {
+        (Some(name), _) => ImageFormat::parse(name)
+            .ok_or_else(|| format!("--format: ожидается svg или png, а не {name}")),
+        (None, Some(path)) => Ok(ImageFormat::from_path(Path::new(path))),
+        (None, None) => Ok(ImageFormat::Svg),
+    }
+}
+
+/* Отчёт: открытый текст шифруется, результат и статистика сводятся в документ */
+fn report(options: &Options) -> Result<(), String> {
+    options.check_known(&[
+        "-k",
+        "--key-file",
+        "-p",
+        "--pipeline",
+        "--format",
+        "--wordlist",
+        "--search",
+        "-i",
+        "--input",
+        "-o",
+        "--output",
+    ])?;
+    let format = report_format(options)?;
+    let (_, pipeline, comment) = load_cipher(options)?;
+    /* Без комментария отчёт называется по файлу ключа или конвейера */
+    let title = if comment.trim().is_empty() {
+        options
+            .value(&["-k", "--key-file", "-p", "--pipeline"])
+            .unwrap_or_default()
+            .to_owned()
+    } else {
+        comment
+    };
+    let plaintext = options.input()?;
+    let ciphertext = pipeline.encrypt(&plaintext).map_err(|e| e.to_string())?;
+    let attacks = report_attacks(options, &pipeline.stages, &ciphertext)?;
+    let report = Report {
+        title,
+        stages: pipeline.stages,
+        plaintext,
+        ciphertext,
+        attacks,
+    };
+    options.output(&report.render(format))
+}
+
+/* Решётка или шаблон для печати */
+fn card(options: &Options) -> Result<(), String> {
+    options.check_known(&[
+        "-k",
+        "--key-file",
+        "--sheet",
+        "--size",
+        "--dpi",
+        "--format",
+        "-o",
+        "--output",
+    ])?;
+    if !options.positional.is_empty() {
+        return Err(format!("лишний аргумент {}", options.positional[0]));
+    }
+    let path = options
+        .value(&["-k", "--key-file"])
+        .ok_or("нужен файл ключа: --key-file <ключ.toml>")?;
+    let file = KeyFile::load(Path::new(path)).map_err(|e| format!("{path}: {e}"))?;
+    let Stage::Grille { key } = file.key else {
+        return Err(format!("{path}: ключ не для решётки"));
+    };
+    let sheet = match options.value(&["--sheet"]) {
+        Some(name) => Sheet::parse(name)
+            .ok_or_else(|| format!("--sheet: ожидается card или template, а не {name}"))?,
+        None => Sheet::Card,
+    };
+    let size = number(options, "--size", card::SIZE_RANGE, card::DEFAULT_SIZE)?;
+    let dpi = number(options, "--dpi", card::DPI_RANGE, card::DEFAULT_DPI)?;
+    let format = image_format(options)?;
+    let bytes = card::render(&key, sheet, size, format, dpi).map_err(|e| e.to_string())?;
+    options.output_bytes(&bytes)
+}
+
+/* Целое число из параметра в допустимых пределах */
+fn number<T>(
+    options: &Options,
+    name: &str,
+    range: RangeInclusive<T>,
+    default: T,
+) -> Result<T, String>
+where
+    T: std::str::FromStr + PartialOrd + std::fmt::Display,
+{
+    match options.value(&[name]) {
+        Some(value) => value
+            .parse()
+            .ok()
+            .filter(|n| range.contains(n))
+            .ok_or_else(|| {
+                format!(
+                    "{name}: ожидается число от {} до {}",
+                    range.start(),
+                    range.end()
+                )
+            }),
+        None => Ok(default),
+    }
+}
+
+/* Параметры заданий; seed — зерно, если --seed не указан */
+fn exercise_config(options: &Options, seed: u64) -> Result<ExerciseConfig, String> {
+    let [cipher] = options.positional.as_slice() else {
+        return Err("ожидается шифр: vigener или grille".to_owned());
+    };
+    let defaults = ExerciseConfig::default();
+    let config = ExerciseConfig {
+        cipher: match cipher.as_str() {
+            "vigener" => ExerciseCipher::Vigener,
+            "grille" => ExerciseCipher::Grille,
+            other => return Err(format!("неизвестный шифр {other}")),
+        },
+        key_length: number(
+            options,
+            "--key-length",
+            1..=exercise::MAX_KEY_LENGTH,
+            defaults.key_length,
+        )?,
+        language: match options.value(&["--language"]).unwrap_or("ru") {
+            "ru" => Language::Russian,
+            "en" => Language::English,
+            other => return Err(format!("--language: ожидается ru или en, а не {other}")),
+        },
+        source: match options.value(&["--text"]).unwrap_or("corpus") {
+            "corpus" => TextSource::Corpus,
+            "random" => TextSource::Random,
+            other => return Err(format!("--text: ожидается corpus или random, а не {other}")),
+        },
+        letters: number(
+            options,
+            "--letters",
+            1..=exercise::MAX_LETTERS,
+            defaults.letters,
+        )?,
+        count: number(options, "--count", 1..=exercise::MAX_COUNT, defaults.count)?,
+        seed: number(options, "--seed", 0..=u64::MAX, seed)?,
+        give_key: options.switch("--give-key", true)?,
+    };
+    if config.cipher == ExerciseCipher::Vigener && config.language != Language::Russian {
+        return Err("Виженер работает только с русским текстом".to_owned());
+    }
+    Ok(config)
+}
+
+fn exercises(options: &Options) -> Result<(), String> {
+    options.check_known(&[
+        "--count",
+        "--seed",
+        "--letters",
+        "--key-length",
+        "--language",
+        "--text",
+        "--corpus",
+        "--give-key",
+        "-o",
+        "--output",
+    ])?;
+    let config = exercise_config(options, rand::thread_rng().gen_range(0..1_000_000))?;
+    let dir = options
+        .value(&["-o", "--output"])
+        .ok_or("нужен каталог для заданий: -o <каталог>")?;
+    let corpus = match options.value(&["--corpus"]) {
+        Some(path) => {
+            let bytes = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+            Some(decode_auto(&bytes).text)
+        }
+        None => None,
+    };
+    let exercises = exercise::generate(&config, corpus.as_deref()).map_err(|e| e.to_string())?;
+    let count = exercise::write_files(Path::new(dir), &config, &exercises)
+        .map_err(|e| format!("{dir}: {e}"))?;
+    println!("{dir}: вариантов {count}, зерно {}", config.seed);
+    Ok(())
+}
+
+/* Возвращает код завершения процесса */
+pub fn run(args: &[String]) -> i32 {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => ("help", args),
+    };
+    let result = Options::parse(rest).and_then(|options| match command {
+        "encrypt" => with_key_file(&options, true),
+        "decrypt" => with_key_file(&options, false),
+        "pipeline" => pipeline(&options),
+        "key" => key(&options),
+        "seal" => seal(&options),
+        "open" => open(&options),
+        "report" => report(&options),
+        "card" => card(&options),
+        "exercises" => exercises(&options),
+        "help" | "-h" | "--help" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        other => Err(format!("неизвестная команда {other}\n\n{USAGE}")),
+    });
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Ошибка: {e}");
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotating_grille::key_from_choices;
+    use std::path::PathBuf;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|&a| a.to_owned()).collect()
+    }
+
+    fn options(list: &[&str]) -> Options {
+        Options::parse(&args(list)).unwrap()
+    }
+
+    /* Пустой каталог для файлов одного теста */
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rotating_net_cli_{}_{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run_in(dir: &Path, list: &[&str]) -> i32 {
+        let list: Vec<String> = list
+            .iter()
+            .map(|a| match a.strip_prefix('@') {
+                Some(file) => dir.join(file).display().to_string(),
+                None => a.to_string(),
+            })
+            .collect();
+        run(&list)
+    }
+
+    fn write_key(dir: &Path, name: &str, key: Stage) {
+        let file = KeyFile {
+            comment: "тестовый ключ".to_owned(),
+            key,
+        };
+        std::fs::write(dir.join(name), file.to_toml()).unwrap();
+    }
+
+    fn vigener() -> Stage {
+        Stage::Vigener {
+            key: "ключ".to_owned(),
+            alphabet_key: String::new(),
+        }
+    }
+
+    fn grille() -> Stage {
+        Stage::Grille {
+            key: key_from_choices(&[1, 3, 0, 2]),
+        }
+    }
+
+    #[test]
+    fn options_are_split_into_named_and_positional() {
+        let parsed = options(&["vigener", "-o", "out", "--seed", "1", "-", "--seed", "2"]);
+        assert_eq!(parsed.positional, ["vigener", "-"]);
+        assert_eq!(parsed.value(&["-o", "--output"]), Some("out"));
+        /* Повторённый параметр: действует последний */
+        assert_eq!(parsed.value(&["--seed"]), Some("2"));
+        assert_eq!(parsed.value(&["--count"]), None);
+
+        assert_eq!(
+            Options::parse(&args(&["-i", "in", "--seed"]))
+                .err()
+                .unwrap(),
+            "после --seed ожидается значение"
+        );
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() {
+        let parsed = options(&["-i", "in", "--colour", "red"]);
+        assert!(parsed.check_known(&["-i", "--colour"]).is_ok());
+        assert_eq!(
+            parsed.check_known(&["-i", "-o"]).unwrap_err(),
+            "неизвестный параметр --colour"
+        );
+    }
+
+    #[test]
+    fn switches_and_numbers_are_checked() {
+        let parsed = options(&["--mac", "off", "--search", "yes", "--count", "7"]);
+        assert_eq!(parsed.switch("--mac", true), Ok(false));
+        assert_eq!(parsed.switch("--give-key", true), Ok(true));
+        assert_eq!(
+            parsed.switch("--search", false).unwrap_err(),
+            "--search: ожидается on или off, а не yes"
+        );
+
+        assert_eq!(number(&parsed, "--count", 1..=10, 3), Ok(7));
+        assert_eq!(number(&parsed, "--letters", 1..=10, 3), Ok(3));
+        assert_eq!(
+            number(&parsed, "--count", 1..=5, 3).unwrap_err(),
+            "--count: ожидается число от 1 до 5"
+        );
+        let parsed = options(&["--size", "десять"]);
+        assert!(number(&parsed, "--size", card::SIZE_RANGE, card::DEFAULT_SIZE).is_err());
+        let parsed = options(&["--size", "20.5"]);
+        assert_eq!(
+            number(&parsed, "--size", card::SIZE_RANGE, card::DEFAULT_SIZE),
+            Ok(20.5)
+        );
+    }
+
+    #[test]
+    fn formats_follow_option_or_extension() {
+        assert_eq!(
+            report_format(&options(&["-o", "отчёт.html"])),
+            Ok(ReportFormat::Html)
+        );
+        assert_eq!(
+            report_format(&options(&["-o", "отчёт.html", "--format", "md"])),
+            Ok(ReportFormat::Markdown)
+        );
+        assert!(report_format(&options(&["--format", "pdf"])).is_err());
+        assert_eq!(
+            image_format(&options(&["-o", "card.PNG"])),
+            Ok(ImageFormat::Png)
+        );
+        assert_eq!(image_format(&options(&[])), Ok(ImageFormat::Svg));
+    }
+
+    #[test]
+    fn exercise_options() {
+        let config = exercise_config(
+            &options(&["grille", "--language", "en", "--give-key", "off"]),
+            42,
+        )
+        .unwrap();
+        assert_eq!(config.cipher, ExerciseCipher::Grille);
+        assert_eq!(config.language, Language::English);
+        assert_eq!(config.seed, 42);
+        assert!(!config.give_key);
+
+        assert!(exercise_config(&options(&["vigener", "--language", "en"]), 1).is_err());
+        assert!(exercise_config(&options(&["vigener", "--count", "0"]), 1).is_err());
+        assert!(exercise_config(&options(&["enigma"]), 1).is_err());
+        assert!(exercise_config(&options(&[]), 1).is_err());
+    }
+
+    #[test]
+    fn commands_fail_on_bad_arguments() {
+        assert_eq!(run(&args(&["help"])), 0);
+        assert_eq!(run(&args(&["frobnicate"])), 1);
+        assert_eq!(run(&args(&["encrypt", "--key-file"])), 1);
+        assert_eq!(run(&args(&["encrypt", "--colour", "red"])), 1);
+        assert_eq!(run(&args(&["seal", "-i", "x", "-o", "y"])), 1);
+        assert_eq!(run(&args(&["key", "enigma", "фраза"])), 1);
+    }
+
+    #[test]
+    fn encrypt_and_decrypt_with_key_file() {
+        let dir = temp_dir("encrypt");
+        write_key(&dir, "key.toml", vigener());
+        std::fs::write(dir.join("plain.txt"), "Привет, мир").unwrap();
+        assert_eq!(
+            run_in(
+                &dir,
+                &[
+                    "encrypt",
+                    "-k",
+                    "@key.toml",
+                    "-i",
+                    "@plain.txt",
+                    "-o",
+                    "@enc.txt"
+                ]
+            ),
+            0
+        );
+        assert_eq!(
+            run_in(
+                &dir,
+                &[
+                    "decrypt",
+                    "-k",
+                    "@key.toml",
+                    "-i",
+                    "@enc.txt",
+                    "-o",
+                    "@dec.txt"
+                ]
+            ),
+            0
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("dec.txt")).unwrap(),
+            "ПРИВЕТМИР"
+        );
+        assert_eq!(run_in(&dir, &["encrypt", "-k", "@missing.toml"]), 1);
+    }
+
+    #[test]
+    fn pipeline_command() {
+        let dir = temp_dir("pipeline");
+        let pipeline = Pipeline {
+            stages: vec![vigener(), grille()],
+        };
+        pipeline.save(&dir.join("cascade.ron")).unwrap();
+        std::fs::write(dir.join("plain.txt"), "ПРИВЕТМИРЭТОПРОВЕРКАКАСКАДАШИФРО").unwrap();
+        let encrypt = [
+            "pipeline",
+            "@cascade.ron",
+            "encrypt",
+            "-i",
+            "@plain.txt",
+            "-o",
+            "@enc.txt",
+        ];
+        assert_eq!(run_in(&dir, &encrypt), 0);
+        let decrypt = [
+            "pipeline",
+            "@cascade.ron",
+            "decrypt",
+            "-i",
+            "@enc.txt",
+            "-o",
+            "@dec.txt",
+        ];
+        assert_eq!(run_in(&dir, &decrypt), 0);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("dec.txt")).unwrap(),
+            "ПРИВЕТМИРЭТОПРОВЕРКАКАСКАДАШИФРО"
+        );
+        assert_eq!(run_in(&dir, &["pipeline", "@cascade.ron", "sideways"]), 1);
+    }
+
+    #[test]
+    fn key_command_derives_key_file() {
+        let dir = temp_dir("key");
+        assert_eq!(
+            run_in(&dir, &["key", "grille", "пароль", "-o", "@grille.toml"]),
+            0
+        );
+        let file = KeyFile::load(&dir.join("grille.toml")).unwrap();
+        assert!(matches!(file.key, Stage::Grille { .. }));
+        assert_eq!(
+            run_in(&dir, &["key", "vigener", "", "-o", "@empty.toml"]),
+            1
+        );
+    }
+
+    #[test]
+    fn seal_and_open_container() {
+        let dir = temp_dir("container");
+        write_key(&dir, "key.toml", grille());
+        write_key(&dir, "other.toml", vigener());
+        std::fs::write(dir.join("plain.txt"), "Attack at dawn").unwrap();
+        let seal = [
+            "seal",
+            "-k",
+            "@key.toml",
+            "-i",
+            "@plain.txt",
+            "-o",
+            "@sealed.bin",
+        ];
+        assert_eq!(run_in(&dir, &seal), 0);
+        let open = [
+            "open",
+            "-k",
+            "@key.toml",
+            "-i",
+            "@sealed.bin",
+            "-o",
+            "@open.txt",
+        ];
+        assert_eq!(run_in(&dir, &open), 0);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("open.txt")).unwrap(),
+            "ATTACKATDAWN"
+        );
+        /* Ключ другого шифра */
+        assert_eq!(
+            run_in(&dir, &["open", "-k", "@other.toml", "-i", "@sealed.bin"]),
+            1
+        );
+    }
+
+    #[test]
+    fn report_command() {
+        let dir = temp_dir("report");
+        write_key(&dir, "key.toml", grille());
+        std::fs::write(
+            dir.join("plain.txt"),
+            "ANOTHERSIMPLEEXAMPLEOFTHEMETHODISTOWRITETHEWORDSOFAMESSAGE",
+        )
+        .unwrap();
+        let report = [
+            "report",
+            "-k",
+            "@key.toml",
+            "--search",
+            "on",
+            "-i",
+            "@plain.txt",
+            "-o",
+            "@r.html",
+        ];
+        assert_eq!(run_in(&dir, &report), 0);
+        let html = std::fs::read_to_string(dir.join("r.html")).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("тестовый ключ"));
+
+        /* Словарь подходит только для Виженера */
+        let wordlist = [
+            "report",
+            "-k",
+            "@key.toml",
+            "--wordlist",
+            "@words.txt",
+            "-i",
+            "@plain.txt",
+        ];
+        assert_eq!(run_in(&dir, &wordlist), 1);
+    }
+
+    #[test]
+    fn card_command() {
+        let dir = temp_dir("card");
+        write_key(&dir, "key.toml", grille());
+        write_key(&dir, "vigener.toml", vigener());
+        let card = [
+            "card",
+            "-k",
+            "@key.toml",
+            "--sheet",
+            "template",
+            "-o",
+            "@t.png",
+        ];
+        assert_eq!(run_in(&dir, &card), 0);
+        let png = std::fs::read(dir.join("t.png")).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        assert_eq!(
+            run_in(&dir, &["card", "-k", "@key.toml", "-o", "@c.svg"]),
+            0
+        );
+        let svg = std::fs::read_to_string(dir.join("c.svg")).unwrap();
+        assert!(svg.starts_with("<svg"));
+
+        assert_eq!(run_in(&dir, &["card", "-k", "@vigener.toml"]), 1);
+        assert_eq!(run_in(&dir, &["card", "-k", "@key.toml", "--dpi", "10"]), 1);
+    }
+
+    #[test]
+    fn exercises_command() {
+        let dir = temp_dir("exercises");
+        let list = [
+            "exercises",
+            "vigener",
+            "--count",
+            "3",
+            "--seed",
+            "5",
+            "-o",
+            "@set",
+        ];
+        assert_eq!(run_in(&dir, &list), 0);
+        let set = dir.join("set");
+        for name in ["variant_01.txt", "variant_03.key.toml", "answers.txt"] {
+            assert!(set.join(name).exists(), "{name}");
+        }
+        let answers = std::fs::read_to_string(set.join("answers.txt")).unwrap();
+        assert_eq!(run_in(&dir, &list), 0);
+        assert_eq!(
+            std::fs::read_to_string(set.join("answers.txt")).unwrap(),
+            answers
+        );
+        assert_eq!(run_in(&dir, &["exercises", "vigener"]), 1);
+    }
+}
@@ -0,0 +1,678 @@
+/* Контейнер шифртекста: заголовок с шифром, параметрами, длиной и контрольной суммой открытого текста */
+
+use crate::error::CipherError;
+use crate::i18n::{tr, trf};
+use crate::live::Direction;
+use crate::notifications::Notifications;
+use crate::pipeline::{Pipeline, Stage};
+use crate::rotating_grille::{grille_letters, key_rows};
+use crate::vigener_progressive::{filter_russian, Alphabet};
+use eframe::egui;
+use hmac::{Hmac, Mac};
+use im_native_dialog::ImNativeFileDialog;
+use sha1::Sha1;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"TICT";
+const VERSION: u8 = 1;
+
+/* Параметры этапов; другие значения пока не поддерживаются */
+const ALPHABET_RUSSIAN: u8 = 0;
+const VIGENER_PROGRESSIVE: u8 = 1;
+const GRILLE_SIZE: u8 = 4;
+const PADDING_RANDOM_LETTERS: u8 = 0;
+
+const HAS_CHECKSUM: u8 = 1;
+const HAS_MAC: u8 = 2;
+
+/* Имитовставка — первые байты HMAC-SHA1 открытого текста под ключом шифра */
+pub const MAC_LEN: usize = 8;
+
+/* Какую вкладку открыть для контейнера */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CipherKind {
+    Vigener,
+    Grille,
+    Pipeline,
+}
+
+impl CipherKind {
+    fn id(self) -> u8 {
+        match self {
+            CipherKind::Vigener => 1,
+            CipherKind::Grille => 2,
+            CipherKind::Pipeline => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> io::Result<Self> {
+        match id {
+            1 => Ok(CipherKind::Vigener),
+            2 => Ok(CipherKind::Grille),
+            3 => Ok(CipherKind::Pipeline),
+            _ => Err(invalid(format!("неизвестный шифр {id}"))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CipherKind::Vigener => tr("Виженер"),
+            CipherKind::Grille => tr("Вращающаяся решётка"),
+            CipherKind::Pipeline => tr("Каскад шифров"),
+        }
+    }
+}
+
+/* Шифр этапа без ключа */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StageKind {
+    Vigener,
+    Grille,
+}
+
+impl StageKind {
+    pub fn of(stage: &Stage) -> Self {
+        match stage {
+            Stage::Vigener { .. } => StageKind::Vigener,
+            Stage::Grille { .. } => StageKind::Grille,
+        }
+    }
+
+    /* Этап с пустым ключом, чтобы пользователь его заполнил */
+    pub fn blank(self) -> Stage {
+        match self {
+            StageKind::Vigener => Stage::Vigener {
+                key: String::new(),
+                alphabet_key: String::new(),
+            },
+            StageKind::Grille => Stage::Grille {
+                key: [[false; 4]; 4],
+            },
+        }
+    }
+
+    /* Открытый текст в том виде, в каком его вернёт расшифрование */
+    pub fn normalize(self, text: &str) -> String {
+        match self {
+            StageKind::Vigener => filter_russian(text.chars()).collect(),
+            StageKind::Grille => grille_letters(text).collect(),
+        }
+    }
+}
+
+/* Ключи этапов в каноническом виде: одинаково работающие ключи дают одни и те же байты */
+fn key_material(stages: &[Stage]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for stage in stages {
+        match stage {
+            Stage::Vigener { key, alphabet_key } => {
+                bytes.push(b'V');
+                bytes.extend(filter_russian(key.chars()).collect::<String>().as_bytes());
+                bytes.push(0);
+                /* Неверный алфавит сюда не доходит: этапы проверяются при шифровании */
+                let alphabet = Alphabet::parse(alphabet_key)
+                    .map_or_else(|_| alphabet_key.clone(), |alphabet| alphabet.letters());
+                bytes.extend(alphabet.as_bytes());
+            }
+            Stage::Grille { key } => {
+                bytes.push(b'G');
+                bytes.extend(key_rows::to_rows(key).concat().as_bytes());
+            }
+        }
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn mac(stages: &[Stage], plaintext: &str) -> [u8; MAC_LEN] {
+    let tag = Hmac::<Sha1>::new_from_slice(&key_material(stages))
+        .expect("HMAC принимает ключ любой длины")
+        .chain_update(plaintext.as_bytes())
+        .finalize()
+        .into_bytes();
+    tag[..MAC_LEN].try_into().unwrap()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Container {
+    pub cipher: CipherKind,
+    pub stages: Vec<StageKind>,
+    /* Длина открытого текста в буквах, без дополнения решётки */
+    pub original_length: usize,
+    /* CRC-32 открытого текста в UTF-8 */
+    pub checksum: Option<u32>,
+    /* Имитовставка открытого текста; без ключа её не подделать */
+    pub mac: Option<[u8; MAC_LEN]>,
+    pub ciphertext: String,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("контейнер обрезан"))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn expect(&mut self, value: u8, what: &str) -> io::Result<()> {
+        match self.u8()? {
+            found if found == value => Ok(()),
+            found => Err(invalid(format!(
+                "неподдерживаемый параметр {what}: {found}"
+            ))),
+        }
+    }
+}
+
+impl Container {
+    /* Упаковывает шифртекст; пустой открытый текст означает, что он неизвестен.
+     * keyed добавляет имитовставку под ключами этапов. Открытый текст должен
+     * расшифровываться из ciphertext, иначе сумма не сойдётся при открытии */
+    pub fn seal(
+        cipher: CipherKind,
+        stages: &[Stage],
+        plaintext: &str,
+        ciphertext: String,
+        keyed: bool,
+    ) -> Result<Self, CipherError> {
+        let kinds: Vec<_> = stages.iter().map(StageKind::of).collect();
+        let normalized = kinds
+            .iter()
+            .fold(plaintext.to_owned(), |text, stage| stage.normalize(&text));
+        let mut container = Container {
+            cipher,
+            stages: kinds,
+            original_length: grille_letters(&ciphertext).count(),
+            checksum: None,
+            mac: None,
+            ciphertext,
+        };
+        if !normalized.is_empty() {
+            container.original_length = normalized.chars().count();
+            let pipeline = Pipeline {
+                stages: stages.to_vec(),
+            };
+            let decrypted = pipeline.decrypt(&container.ciphertext)?;
+            if container.trim(&decrypted) != normalized {
+                return Err(CipherError::PlaintextMismatch);
+            }
+            container.checksum = Some(crc32fast::hash(normalized.as_bytes()));
+            container.mac = keyed.then(|| mac(stages, &normalized));
+        }
+        Ok(container)
+    }
+
+    /* Отбрасывает дополнение решётки в конце расшифрованного текста */
+    pub fn trim(&self, decrypted: &str) -> String {
+        decrypted.chars().take(self.original_length).collect()
+    }
+
+    /* Сверяет контрольную сумму и имитовставку; None, если проверять нечего */
+    pub fn verify(&self, plaintext: &str, stages: &[Stage]) -> Option<bool> {
+        if self.checksum.is_none() && self.mac.is_none() {
+            return None;
+        }
+        let checksum_ok = self
+            .checksum
+            .is_none_or(|checksum| crc32fast::hash(plaintext.as_bytes()) == checksum);
+        let mac_ok = self.mac.is_none_or(|tag| mac(stages, plaintext) == tag);
+        Some(checksum_ok && mac_ok)
+    }
+
+    /* Расшифровывает, обрезает дополнение и проверяет целостность */
+    pub fn open(&self, stages: &[Stage]) -> Result<String, CipherError> {
+        if stages
+            .iter()
+            .map(StageKind::of)
+            .ne(self.stages.iter().copied())
+        {
+            return Err(CipherError::StageMismatch);
+        }
+        let pipeline = Pipeline {
+            stages: stages.to_vec(),
+        };
+        let plaintext = self.trim(&pipeline.decrypt(&self.ciphertext)?);
+        match self.verify(&plaintext, stages) {
+            Some(false) => Err(CipherError::IntegrityCheckFailed),
+            _ => Ok(plaintext),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(self.cipher.id());
+        bytes.push(self.stages.len() as u8);
+        for stage in &self.stages {
+            match stage {
+                StageKind::Vigener => bytes.extend([
+                    CipherKind::Vigener.id(),
+                    ALPHABET_RUSSIAN,
+                    VIGENER_PROGRESSIVE,
+                ]),
+                StageKind::Grille => {
+                    bytes.extend([CipherKind::Grille.id(), GRILLE_SIZE, PADDING_RANDOM_LETTERS])
+                }
+            }
+        }
+        bytes.extend((self.original_length as u32).to_le_bytes());
+        let mut flags = 0;
+        if self.checksum.is_some() {
+            flags |= HAS_CHECKSUM;
+        }
+        if self.mac.is_some() {
+            flags |= HAS_MAC;
+        }
+        bytes.push(flags);
+        if let Some(checksum) = self.checksum {
+            bytes.extend(checksum.to_le_bytes());
+        }
+        if let Some(tag) = self.mac {
+            bytes.extend(tag);
+        }
+        bytes.extend((self.ciphertext.len() as u32).to_le_bytes());
+        bytes.extend(self.ciphertext.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(invalid("это не контейнер шифртекста"));
+        }
+        if reader.u8()? != VERSION {
+            return Err(invalid("неподдерживаемая версия контейнера"));
+        }
+
+        let cipher = CipherKind::from_id(reader.u8()?)?;
+        let stage_count = reader.u8()?;
+        let stages = (0..stage_count)
+            .map(|_| match CipherKind::from_id(reader.u8()?)? {
+                CipherKind::Vigener => {
+                    reader.expect(ALPHABET_RUSSIAN, "алфавит")?;
+                    reader.expect(VIGENER_PROGRESSIVE, "вариант Виженера")?;
+                    Ok(StageKind::Vigener)
+                }
+                CipherKind::Grille => {
+                    reader.expect(GRILLE_SIZE, "размер решётки")?;
+                    reader.expect(PADDING_RANDOM_LETTERS, "дополнение")?;
+                    Ok(StageKind::Grille)
+                }
+                CipherKind::Pipeline => Err(invalid("каскад не может быть этапом каскада")),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let consistent = match cipher {
+            CipherKind::Vigener => stages == [StageKind::Vigener],
+            CipherKind::Grille => stages == [StageKind::Grille],
+            CipherKind::Pipeline => !stages.is_empty(),
+        };
+        if !consistent {
+            return Err(invalid("этапы не соответствуют шифру контейнера"));
+        }
+
+        let original_length = reader.u32()? as usize;
+        let flags = reader.u8()?;
+        if flags & !(HAS_CHECKSUM | HAS_MAC) != 0 {
+            return Err(invalid(format!("неизвестные флаги {flags}")));
+        }
+        let checksum = match flags & HAS_CHECKSUM {
+            0 => None,
+            _ => Some(reader.u32()?),
+        };
+        let mac = match flags & HAS_MAC {
+            0 => None,
+            _ => Some(reader.take(MAC_LEN)?.try_into().unwrap()),
+        };
+        let length = reader.u32()? as usize;
+        let ciphertext = String::from_utf8(reader.take(length)?.to_vec())
+            .map_err(|_| invalid("шифртекст не в UTF-8"))?;
+        if reader.pos != bytes.len() {
+            return Err(invalid("лишние данные после шифртекста"));
+        }
+
+        Ok(Container {
+            cipher,
+            stages,
+            original_length,
+            checksum,
+            mac,
+            ciphertext,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CipherError> {
+        Ok(Self::from_bytes(&std::fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CipherError> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+}
+
+/* Кнопка «Сохранить контейнер» и заголовок загруженного контейнера для вкладки */
+pub struct ContainerSlot {
+    write_dialog: ImNativeFileDialog<Option<PathBuf>>,
+    pending: Option<Container>,
+    keyed: bool,
+    pub loaded: Option<Container>,
+}
+
+impl Default for ContainerSlot {
+    fn default() -> Self {
+        ContainerSlot {
+            write_dialog: Default::default(),
+            pending: None,
+            keyed: true,
+            loaded: None,
+        }
+    }
+}
+
+impl ContainerSlot {
+    /* Заголовок действует, пока шифртекст во вкладке не изменён */
+    fn header(&self, ciphertext: &str) -> Option<&Container> {
+        self.loaded
+            .as_ref()
+            .filter(|container| container.ciphertext == ciphertext)
+    }
+
+    /* Обрезает дополнение, если шифртекст пришёл из контейнера */
+    pub fn finish(&self, ciphertext: &str, decrypted: String) -> String {
+        match self.header(ciphertext) {
+            Some(container) => container.trim(&decrypted),
+            None => decrypted,
+        }
+    }
+
+    pub fn finish_live(
+        &self,
+        ciphertext: &str,
+        result: Option<(Direction, Result<String, CipherError>)>,
+    ) -> Option<(Direction, Result<String, CipherError>)> {
+        match result? {
+            (Direction::Decrypt, Ok(text)) => {
+                Some((Direction::Decrypt, Ok(self.finish(ciphertext, text))))
+            }
+            other => Some(other),
+        }
+    }
+
+    /* Проверяет целостность после расшифрования кнопкой */
+    pub fn check(
+        &self,
+        ciphertext: &str,
+        plaintext: &str,
+        stages: &[Stage],
+        notifications: &mut Notifications,
+    ) {
+        let Some(container) = self.header(ciphertext) else {
+            return;
+        };
+        match container.verify(plaintext, stages) {
+            Some(true) if container.mac.is_some() => {
+                notifications.info(tr("Имитовставка контейнера совпала: ключ верный"))
+            }
+            Some(true) => notifications.info(tr("Контрольная сумма контейнера совпала")),
+            Some(false) => {
+                notifications.warning(trf("Контейнер: {}", &[&CipherError::IntegrityCheckFailed]))
+            }
+            None => {}
+        }
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        cipher: CipherKind,
+        stages: &[Stage],
+        plaintext: &str,
+        ciphertext: &str,
+        notifications: &mut Notifications,
+    ) {
+        match self.write_dialog.check() {
+            Some(Ok(Some(path))) => {
+                if let Some(container) = self.pending.take() {
+                    match container.save(&path) {
+                        Ok(()) => {
+                            notifications.info(trf("Сохранён контейнер {}", &[&path.display()]))
+                        }
+                        Err(e) => notifications
+                            .error(trf("Не удалось записать {}: {}", &[&path.display(), &e])),
+                    }
+                }
+            }
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
+
+        if let Some(container) = self.header(ciphertext) {
+            let checksum = match container.checksum {
+                Some(checksum) => format!("CRC-32 {checksum:08x}"),
+                None => tr("без контрольной суммы").to_owned(),
+            };
+            let mac = match container.mac {
+                Some(_) => tr(", с имитовставкой"),
+                None => "",
+            };
+            ui.label(trf(
+                "Из контейнера: {} букв, {}{}",
+                &[&container.original_length, &checksum, &mac],
+            ));
+        }
+        ui.checkbox(&mut self.keyed, "MAC").on_hover_text(tr(
+            "Добавить имитовставку: неверный ключ или подмена шифртекста будут обнаружены",
+        ));
+        if ui
+            .add_enabled(
+                !ciphertext.is_empty(),
+                egui::Button::new(tr("Сохранить контейнер...")),
+            )
+            .on_hover_text(tr(
+                "Шифртекст с заголовком: шифр, параметры, длина и контрольная сумма",
+            ))
+            .clicked()
+        {
+            match Container::seal(cipher, stages, plaintext, ciphertext.to_owned(), self.keyed) {
+                Ok(container) => {
+                    self.pending = Some(container);
+                    if let Err(e) = self.write_dialog.show_save_single_file(None) {
+                        notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                    }
+                }
+                Err(e) => notifications.warning(trf("Контейнер: {}", &[&e])),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotating_grille::key_from_choices;
+
+    fn cascade() -> Vec<Stage> {
+        vec![
+            Stage::Vigener {
+                key: "ключ".to_owned(),
+                alphabet_key: String::new(),
+            },
+            Stage::Grille {
+                key: key_from_choices(&[3, 1, 0, 2]),
+            },
+        ]
+    }
+
+    fn sealed(stages: &[Stage], keyed: bool) -> Container {
+        let plaintext = "Съешь же ещё этих мягких французских булок";
+        let ciphertext = Pipeline {
+            stages: stages.to_vec(),
+        }
+        .encrypt(plaintext)
+        .unwrap();
+        Container::seal(CipherKind::Pipeline, stages, plaintext, ciphertext, keyed).unwrap()
+    }
+
+    #[test]
+    fn grille_padding_is_trimmed() {
+        let stages = [Stage::Grille {
+            key: key_from_choices(&[0, 1, 2, 3]),
+        }];
+        let plaintext = "Привет, мир";
+        let ciphertext = stages[0].encrypt(plaintext).unwrap();
+        let container =
+            Container::seal(CipherKind::Grille, &stages, plaintext, ciphertext, true).unwrap();
+
+        let read = Container::from_bytes(&container.to_bytes()).unwrap();
+        assert_eq!(read, container);
+        assert_eq!(read.original_length, 9);
+        assert_eq!(read.open(&stages).unwrap(), "ПРИВЕТМИР");
+    }
+
+    #[test]
+    fn wrong_key_is_reported() {
+        let stages = cascade();
+        let container = sealed(&stages, true);
+        assert!(container.open(&stages).is_ok());
+
+        /* Ключ, записанный иначе, но шифрующий так же, подходит */
+        let mut same = stages.clone();
+        same[0] = Stage::Vigener {
+            key: "К-Л-Ю-Ч".to_owned(),
+            alphabet_key: String::new(),
+        };
+        assert!(container.open(&same).is_ok());
+
+        let mut wrong = stages.clone();
+        wrong[0] = Stage::Vigener {
+            key: "замок".to_owned(),
+            alphabet_key: String::new(),
+        };
+        assert!(matches!(
+            container.open(&wrong),
+            Err(CipherError::IntegrityCheckFailed)
+        ));
+        let mut wrong = stages;
+        wrong[1] = Stage::Grille {
+            key: key_from_choices(&[0, 0, 0, 0]),
+        };
+        assert!(matches!(
+            container.open(&wrong),
+            Err(CipherError::IntegrityCheckFailed)
+        ));
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let stages = cascade();
+        let container = sealed(&stages, true);
+
+        /* Замена одной буквы шифртекста */
+        let mut tampered = container.clone();
+        let mut letters: Vec<char> = tampered.ciphertext.chars().collect();
+        let i = letters.iter().position(|c| c.is_alphabetic()).unwrap();
+        letters[i] = if letters[i] == 'А' { 'Б' } else { 'А' };
+        tampered.ciphertext = letters.into_iter().collect();
+        assert!(matches!(
+            tampered.open(&stages),
+            Err(CipherError::IntegrityCheckFailed)
+        ));
+
+        /* Без ключа нельзя подменить текст, пересчитав длину и CRC-32 */
+        let mut forged = container.clone();
+        let other = "ПЕРЕВЕДИТЕСТОРУБЛЕЙ";
+        forged.ciphertext = Pipeline {
+            stages: stages.clone(),
+        }
+        .encrypt(other)
+        .unwrap();
+        forged.original_length = other.chars().count();
+        forged.checksum = Some(crc32fast::hash(other.as_bytes()));
+        assert!(matches!(
+            forged.open(&stages),
+            Err(CipherError::IntegrityCheckFailed)
+        ));
+
+        /* Повреждение самой имитовставки в файле */
+        let mut bytes = container.to_bytes();
+        let tag_end = bytes.len() - 4 - container.ciphertext.len();
+        bytes[tag_end - 1] ^= 1;
+        let damaged = Container::from_bytes(&bytes).unwrap();
+        assert!(matches!(
+            damaged.open(&stages),
+            Err(CipherError::IntegrityCheckFailed)
+        ));
+    }
+
+    #[test]
+    fn refuses_plaintext_of_another_ciphertext() {
+        let stages = cascade();
+        let pipeline = Pipeline {
+            stages: stages.clone(),
+        };
+        let ciphertext = pipeline.encrypt("Съешь же ещё этих булок").unwrap();
+        /* Текст поправили после шифрования: с такой суммой контейнер бы не открылся */
+        assert!(matches!(
+            Container::seal(
+                CipherKind::Pipeline,
+                &stages,
+                "Съешь же ещё этих булочек",
+                ciphertext.clone(),
+                true
+            ),
+            Err(CipherError::PlaintextMismatch)
+        ));
+        /* Регистр и знаки препинания расшифровка и так не сохраняет */
+        let container = Container::seal(
+            CipherKind::Pipeline,
+            &stages,
+            "съешь, же, ещё этих булок!",
+            ciphertext,
+            true,
+        )
+        .unwrap();
+        assert_eq!(container.open(&stages).unwrap(), "СЪЕШЬЖЕЕЩЁЭТИХБУЛОК");
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let stages = [Stage::Vigener {
+            key: "ключ".to_owned(),
+            alphabet_key: String::new(),
+        }];
+        let container =
+            Container::seal(CipherKind::Vigener, &stages, "", "ШИФР".to_owned(), true).unwrap();
+        assert_eq!((container.checksum, container.mac), (None, None));
+        assert!(sealed(&cascade(), false).mac.is_none());
+        let bytes = container.to_bytes();
+        assert_eq!(Container::from_bytes(&bytes).unwrap(), container);
+
+        assert!(Container::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Container::from_bytes(b"TILM").is_err());
+        let mut wrong_cipher = bytes.clone();
+        wrong_cipher[5] = CipherKind::Grille.id();
+        assert!(Container::from_bytes(&wrong_cipher).is_err());
+        let mut wrong_alphabet = bytes;
+        wrong_alphabet[8] = 7;
+        assert!(Container::from_bytes(&wrong_alphabet).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+/* Посимвольное сравнение текстов: исходного и прошедшего через шифр туда и обратно
+ * или двух вариантов расшифровки */
+
+use crate::error::CipherError;
+use crate::i18n::{tr, trf};
+use crate::notifications::Notifications;
+use eframe::egui;
+use egui::text::{LayoutJob, TextFormat};
+use egui::{Color32, Stroke};
+
+/* Больше правок алгоритм Майерса не ищет: таблица ходов растёт как квадрат их числа */
+const MAX_EDITS: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Same(String),
+    /* Есть только в первом тексте */
+    Removed(String),
+    /* Есть только во втором тексте */
+    Added(String),
+}
+
+impl Change {
+    pub fn text(&self) -> &str {
+        match self {
+            Change::Same(text) | Change::Removed(text) | Change::Added(text) => text,
+        }
+    }
+}
+
+/* Соседние символы одного вида склеиваются в один фрагмент */
+fn push(changes: &mut Vec<Change>, change: Change) {
+    match (changes.last_mut(), &change) {
+        (Some(Change::Same(last)), Change::Same(c))
+        | (Some(Change::Removed(last)), Change::Removed(c))
+        | (Some(Change::Added(last)), Change::Added(c)) => last.push_str(c),
+        _ => changes.push(change),
+    }
+}
+
+/* Алгоритм Майерса; None, если правок больше max_edits.
+ * Правки возвращаются с конца, по одному символу */
+fn myers(a: &[char], b: &[char], max_edits: usize) -> Option<Vec<Change>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_edits = max_edits.min(a.len() + b.len()) as isize;
+    let offset = max_edits + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+    /* Перед шагом d сохраняются концы путей для k от -d-1 до d+1 */
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=max_edits {
+        trace.push(v[at(-d - 1)..=at(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(a, b, &trace));
+            }
+        }
+    }
+    None
+}
+
+fn backtrack(a: &[char], b: &[char], trace: &[Vec<isize>]) -> Vec<Change> {
+    let (mut x, mut y) = (a.len() as isize, b.len() as isize);
+    let mut changes = Vec::new();
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let get = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = get(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            changes.push(Change::Same(a[x as usize - 1].to_string()));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                changes.push(Change::Added(b[y as usize - 1].to_string()));
+            } else {
+                changes.push(Change::Removed(a[x as usize - 1].to_string()));
+            }
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    changes
+}
+
+/* Кратчайший набор удалений и вставок, переводящий old в new */
+pub fn diff(old: &str, new: &str) -> Vec<Change> {
+    let a: Vec<char> = old.chars().collect();
+    let b: Vec<char> = new.chars().collect();
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (middle_a, middle_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut changes = Vec::new();
+    push(&mut changes, Change::Same(a[..prefix].iter().collect()));
+    match myers(middle_a, middle_b, MAX_EDITS) {
+        Some(middle) => {
+            for change in middle.into_iter().rev() {
+                push(&mut changes, change);
+            }
+        }
+        /* Тексты совсем разные: подробное сравнение не нужно */
+        None => {
+            push(&mut changes, Change::Removed(middle_a.iter().collect()));
+            push(&mut changes, Change::Added(middle_b.iter().collect()));
+        }
+    }
+    push(
+        &mut changes,
+        Change::Same(a[a.len() - suffix..].iter().collect()),
+    );
+    changes.retain(|change| !change.text().is_empty());
+    changes
+}
+
+/* Номер первого отличающегося символа первого текста; None, если тексты совпадают */
+pub fn first_difference(changes: &[Change]) -> Option<usize> {
+    let mut position = 0;
+    for change in changes {
+        match change {
+            Change::Same(text) => position += text.chars().count(),
+            _ => return Some(position),
+        }
+    }
+    None
+}
+
+/* Число символов, которые пришлось удалить или вставить */
+pub fn edit_count(changes: &[Change]) -> usize {
+    changes
+        .iter()
+        .filter(|change| !matches!(change, Change::Same(_)))
+        .map(|change| change.text().chars().count())
+        .sum()
+}
+
+fn highlighted(ui: &egui::Ui, changes: &[Change]) -> LayoutJob {
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let color = ui.visuals().text_color();
+    let mut job = LayoutJob::default();
+    for change in changes {
+        let format = match change {
+            Change::Same(_) => TextFormat::simple(font_id.clone(), color),
+            Change::Removed(_) => TextFormat {
+                background: Color32::from_rgba_unmultiplied(220, 50, 50, 90),
+                strikethrough: Stroke::new(1.0, color),
+                ..TextFormat::simple(font_id.clone(), color)
+            },
+            Change::Added(_) => TextFormat {
+                background: Color32::from_rgba_unmultiplied(40, 170, 70, 90),
+                ..TextFormat::simple(font_id.clone(), color)
+            },
+        };
+        job.append(change.text(), 0.0, format);
+    }
+    job
+}
+
+/* Панель сравнения: два текста и подсветка отличий второго от первого */
+#[derive(Default)]
+pub struct ComparePanel {
+    first: String,
+    second: String,
+    /* Результат для текущих текстов; сбрасывается при их изменении */
+    changes: Option<Vec<Change>>,
+}
+
+impl ComparePanel {
+    /* Кнопки «→ первый» и «→ второй» берут открытый текст вкладки, например две расшифровки.
+     * round_trip зашифровывает и расшифровывает открытый текст текущим ключом */
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        plaintext: &str,
+        round_trip: impl FnOnce(&str) -> Result<String, CipherError>,
+        notifications: &mut Notifications,
+    ) {
+        ui.horizontal(|ui| {
+            if ui
+                .button(tr("Проверить обратимость"))
+                .on_hover_text(tr(
+                    "Сравнить открытый текст с результатом зашифрования и расшифрования",
+                ))
+                .clicked()
+            {
+                match round_trip(plaintext) {
+                    Ok(text) => {
+                        self.first = plaintext.to_owned();
+                        self.second = text;
+                        self.changes = None;
+                    }
+                    Err(e) => notifications.warning(e.to_string()),
+                }
+            }
+            if ui.button(tr("Открытый текст → первый")).clicked() {
+                self.first = plaintext.to_owned();
+                self.changes = None;
+            }
+            if ui.button(tr("Открытый текст → второй")).clicked() {
+                self.second = plaintext.to_owned();
+                self.changes = None;
+            }
+        });
+        ui.columns(2, |column| {
+            column[0].label(tr("Первый текст"));
+            if column[0].text_edit_multiline(&mut self.first).changed() {
+                self.changes = None;
+            }
+            column[1].label(tr("Второй текст"));
+            if column[1].text_edit_multiline(&mut self.second).changed() {
+                self.changes = None;
+            }
+        });
+
+        let changes = self
+            .changes
+            .get_or_insert_with(|| diff(&self.first, &self.second));
+        match first_difference(changes) {
+            None => ui.label(tr("Тексты совпадают")),
+            Some(position) => ui.label(trf(
+                "Отличий: {} символов, первое в позиции {}",
+                &[&edit_count(changes), &(position + 1)],
+            )),
+        };
+        egui::ScrollArea::vertical()
+            .id_source("compare_scroll")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                ui.label(highlighted(ui, changes));
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /* Применяет правки к первому тексту */
+    fn apply(changes: &[Change]) -> (String, String) {
+        let mut old = String::new();
+        let mut new = String::new();
+        for change in changes {
+            match change {
+                Change::Same(text) => {
+                    old.push_str(text);
+                    new.push_str(text);
+                }
+                Change::Removed(text) => old.push_str(text),
+                Change::Added(text) => new.push_str(text),
+            }
+        }
+        (old, new)
+    }
+
+    #[test]
+    fn highlights_single_substitution() {
+        let changes = diff("ПРИВЕТ МИР", "ПРИВЕТ МЫР");
+        assert_eq!(
+            changes,
+            [
+                Change::Same("ПРИВЕТ М".to_owned()),
+                Change::Removed("И".to_owned()),
+                Change::Added("Ы".to_owned()),
+                Change::Same("Р".to_owned()),
+            ]
+        );
+        assert_eq!(first_difference(&changes), Some(8));
+        assert_eq!(edit_count(&changes), 2);
+        assert_eq!(first_difference(&diff("шифр", "шифр")), None);
+        assert_eq!(diff("", ""), []);
+    }
+
+    #[test]
+    fn appended_tail_is_one_insertion() {
+        /* Так выглядит расшифровка решётки с дополнением последнего блока */
+        let changes = diff("ШИФР", "ШИФРЖЭЦБ");
+        assert_eq!(
+            changes,
+            [
+                Change::Same("ШИФР".to_owned()),
+                Change::Added("ЖЭЦБ".to_owned()),
+            ]
+        );
+        assert_eq!(first_difference(&changes), Some(4));
+        assert_eq!(edit_count(&changes), 4);
+    }
+
+    proptest! {
+        #[test]
+        fn changes_rebuild_both_texts(old in "[абв ]{0,40}", new in "[абв ]{0,40}") {
+            let changes = diff(&old, &new);
+            prop_assert_eq!(apply(&changes), (old.clone(), new.clone()));
+            /* Правок не больше, чем при замене текста целиком */
+            prop_assert!(edit_count(&changes) <= old.chars().count() + new.chars().count());
+        }
+
+        #[test]
+        fn edit_count_is_minimal_for_one_insertion(text in "[А-Я]{1,30}", c in "[a-z]", i in 0usize..30) {
+            let i = i % (text.chars().count() + 1);
+            let mut changed: Vec<char> = text.chars().collect();
+            changed.insert(i, c.chars().next().unwrap());
+            let changed: String = changed.into_iter().collect();
+            prop_assert_eq!(edit_count(&diff(&text, &changed)), 1);
+            prop_assert_eq!(first_difference(&diff(&text, &changed)), Some(i));
+        }
+    }
+
+    #[test]
+    fn very_different_texts_are_replaced_whole() {
+        let old = "А".repeat(3000);
+        let new = "Б".repeat(3000);
+        let changes = diff(&old, &new);
+        assert_eq!(
+            changes,
+            [Change::Removed(old.clone()), Change::Added(new.clone())]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+/* Ошибки шифров; сообщения переводятся через каталог, как и весь интерфейс */
+
+use crate::i18n::{tr, trf};
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum CipherError {
+    EmptyKey,
+    /* Буква не из алфавита шифра */
+    InvalidCharacter(char),
+    /* Перестановка алфавита таблицы без некоторых букв или с повторами */
+    InvalidAlphabet,
+    /* В какой-то орбите решётки нет отверстия или их несколько */
+    InvalidGrille,
+    /* Число букв шифртекста решётки не кратно размеру блока */
+    MalformedCiphertext {
+        letters: usize,
+    },
+    /* Контрольная сумма или имитовставка контейнера не совпала */
+    IntegrityCheckFailed,
+    /* Открытый текст для контейнера не совпадает с расшифровкой шифртекста */
+    PlaintextMismatch,
+    /* Шифры ключа не совпадают с записанными в контейнере */
+    StageMismatch,
+    EmptyPipeline,
+    /* В тексте для заданий нет букв алфавита шифра */
+    EmptyCorpus,
+    /* Ошибка этапа каскада, этапы нумеруются с 1 */
+    Stage {
+        index: usize,
+        source: Box<CipherError>,
+    },
+    Io(io::Error),
+}
+
+impl CipherError {
+    pub fn in_stage(self, index: usize) -> Self {
+        CipherError::Stage {
+            index,
+            source: Box::new(self),
+        }
+    }
+}
+
+/* На языке интерфейса; в командной строке всегда на русском */
+impl fmt::Display for CipherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CipherError::*;
+        let message = match self {
+            EmptyKey => tr("пустой ключ").to_owned(),
+            InvalidCharacter(c) => trf("недопустимый символ «{}» в ключе", &[c]),
+            InvalidAlphabet => {
+                tr("алфавит таблицы должен содержать каждую из 33 букв ровно один раз").to_owned()
+            }
+            InvalidGrille => tr("ключ решётки неполон или с конфликтами").to_owned(),
+            MalformedCiphertext { letters } => {
+                trf("шифртекст из {} букв не делится на блоки по 16", &[letters])
+            }
+            IntegrityCheckFailed => tr("неверный ключ или повреждённый шифртекст").to_owned(),
+            PlaintextMismatch => {
+                tr("открытый текст не совпадает с расшифровкой шифртекста").to_owned()
+            }
+            StageMismatch => tr("этапы ключа не совпадают с этапами контейнера").to_owned(),
+            EmptyPipeline => tr("в конвейере нет ни одного этапа").to_owned(),
+            EmptyCorpus => tr("в корпусе нет букв нужного алфавита").to_owned(),
+            Stage { index, source } => trf("этап {}: {}", &[index, source]),
+            /* Сообщения файловых форматов и системы не переводятся */
+            Io(e) => e.to_string(),
+        };
+        f.write_str(&message)
+    }
+}
+
+impl std::error::Error for CipherError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CipherError::Stage { source, .. } => Some(source.as_ref()),
+            CipherError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CipherError {
+    fn from(e: io::Error) -> Self {
+        CipherError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::{translate, Locale};
+
+    #[test]
+    fn messages_in_both_languages() {
+        let error = CipherError::InvalidGrille.in_stage(2);
+        assert_eq!(
+            error.to_string(),
+            "этап 2: ключ решётки неполон или с конфликтами"
+        );
+        assert_eq!(
+            translate(Locale::English, "ключ решётки неполон или с конфликтами"),
+            "the grille key is incomplete or has conflicting holes"
+        );
+        let io = CipherError::from(io::Error::new(io::ErrorKind::NotFound, "нет файла"));
+        assert!(std::error::Error::source(&io).is_some());
+    }
+}
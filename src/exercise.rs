@@ -0,0 +1,548 @@
+/* Генератор вариантов заданий: случайный ключ, открытый текст из случайных букв
+ * или отрывок корпуса, шифртекст и ответы. Одно и то же зерно даёт те же варианты */
+
+use crate::error::CipherError;
+use crate::i18n::{tr, trf};
+use crate::key_file::KeyFile;
+use crate::language_model::{Language, LANGUAGES};
+use crate::notifications::Notifications;
+use crate::pipeline::Stage;
+use crate::report::grille_ascii;
+use crate::rotating_grille::key_from_choices;
+use crate::text_encoding::decode_auto;
+use crate::vigener_progressive::{filter_russian, ALPHABET};
+use eframe::egui;
+use im_native_dialog::ImNativeFileDialog;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const MAX_COUNT: usize = 500;
+pub const MAX_LETTERS: usize = 5000;
+pub const MAX_KEY_LENGTH: usize = 30;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ExerciseCipher {
+    Vigener,
+    Grille,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum TextSource {
+    Random,
+    Corpus,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExerciseConfig {
+    pub cipher: ExerciseCipher,
+    pub key_length: usize,
+    /* Язык текста для решётки; Виженер работает только с русским */
+    pub language: Language,
+    pub source: TextSource,
+    pub letters: usize,
+    pub count: usize,
+    pub seed: u64,
+    /* Ключ в задании — упражнение на расшифрование, без ключа — на криптоанализ */
+    pub give_key: bool,
+}
+
+impl Default for ExerciseConfig {
+    fn default() -> Self {
+        ExerciseConfig {
+            cipher: ExerciseCipher::Vigener,
+            key_length: 5,
+            language: Language::Russian,
+            source: TextSource::Corpus,
+            letters: 200,
+            count: 20,
+            seed: 1,
+            give_key: true,
+        }
+    }
+}
+
+impl ExerciseConfig {
+    pub fn text_language(&self) -> Language {
+        match self.cipher {
+            ExerciseCipher::Vigener => Language::Russian,
+            ExerciseCipher::Grille => self.language,
+        }
+    }
+
+    /* Решётка шифрует целые блоки: без дополнения случайными буквами
+     * варианты не повторялись бы при том же зерне */
+    pub fn text_letters(&self) -> usize {
+        match self.cipher {
+            ExerciseCipher::Vigener => self.letters.max(1),
+            ExerciseCipher::Grille => self.letters.max(1).div_ceil(16) * 16,
+        }
+    }
+
+    /* Буквы, которые шифр действительно обрабатывает */
+    fn is_letter(&self, c: char) -> bool {
+        match self.cipher {
+            ExerciseCipher::Vigener => filter_russian(std::iter::once(c)).next().is_some(),
+            ExerciseCipher::Grille => c.is_alphabetic(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exercise {
+    pub number: usize,
+    pub key: Stage,
+    pub plaintext: String,
+    pub ciphertext: String,
+}
+
+/* Равномерное число от 0 до n - 1. Поток ChaCha8 одинаков во всех версиях
+ * rand_chacha, а своя выборка вместо gen_range не зависит от версии rand, так что
+ * варианты по номеру остаются прежними */
+fn below(rng: &mut impl RngCore, n: usize) -> usize {
+    let n = n as u64;
+    /* Отбрасываются значения из неполного последнего отрезка длины n */
+    let limit = u64::MAX - u64::MAX % n;
+    loop {
+        let value = rng.next_u64();
+        if value < limit {
+            return (value % n) as usize;
+        }
+    }
+}
+
+/* Случайные буквы группами по пять */
+fn random_text(alphabet: &[char], letters: usize, rng: &mut impl RngCore) -> String {
+    let mut text = String::new();
+    for i in 0..letters {
+        if i > 0 && i % 5 == 0 {
+            text.push(' ');
+        }
+        text.push(alphabet[below(rng, alphabet.len())]);
+    }
+    text
+}
+
+/* Отрывок с начала случайного слова ровно из letters букв; в конце корпуса чтение
+ * продолжается с его начала */
+fn passage(
+    corpus: &str,
+    letters: usize,
+    is_letter: impl Fn(char) -> bool,
+    rng: &mut impl RngCore,
+) -> Result<String, CipherError> {
+    let chars: Vec<char> = corpus.chars().collect();
+    let starts: Vec<usize> = (0..chars.len())
+        .filter(|&i| is_letter(chars[i]) && (i == 0 || !is_letter(chars[i - 1])))
+        .collect();
+    if starts.is_empty() {
+        return Err(CipherError::EmptyCorpus);
+    }
+    let mut i = starts[below(rng, starts.len())];
+    let mut text = String::new();
+    let mut count = 0;
+    while count < letters {
+        text.push(chars[i]);
+        if is_letter(chars[i]) {
+            count += 1;
+        }
+        i += 1;
+        if i == chars.len() {
+            i = 0;
+            text.push(' ');
+        }
+    }
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/* corpus — свой текст для отрывков, без него берётся встроенный корпус языка */
+pub fn generate(
+    config: &ExerciseConfig,
+    corpus: Option<&str>,
+) -> Result<Vec<Exercise>, CipherError> {
+    let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+    let language = config.text_language();
+    let corpus = corpus.unwrap_or(language.corpus());
+    (1..=config.count)
+        .map(|number| {
+            let key = match config.cipher {
+                ExerciseCipher::Vigener => Stage::Vigener {
+                    key: (0..config.key_length.max(1))
+                        .map(|_| ALPHABET[below(&mut rng, ALPHABET.len())])
+                        .collect(),
+                    alphabet_key: String::new(),
+                },
+                ExerciseCipher::Grille => Stage::Grille {
+                    key: key_from_choices(&[(); 4].map(|_| below(&mut rng, 4))),
+                },
+            };
+            let plaintext = match config.source {
+                TextSource::Random => {
+                    random_text(language.alphabet(), config.text_letters(), &mut rng)
+                }
+                TextSource::Corpus => passage(
+                    corpus,
+                    config.text_letters(),
+                    |c| config.is_letter(c),
+                    &mut rng,
+                )?,
+            };
+            let ciphertext = key.encrypt(&plaintext)?;
+            Ok(Exercise {
+                number,
+                key,
+                plaintext,
+                ciphertext,
+            })
+        })
+        .collect()
+}
+
+fn describe_key(key: &Stage) -> String {
+    match key {
+        Stage::Vigener { key, .. } => trf("Ключ: {}", &[key]),
+        Stage::Grille { key } => format!(
+            "{}\n{}",
+            tr("Ключ (# — отверстие):"),
+            grille_ascii(key).trim_end()
+        ),
+    }
+}
+
+pub fn task_text(exercise: &Exercise, give_key: bool) -> String {
+    let mut text = trf("Вариант {}", &[&exercise.number]);
+    text.push('\n');
+    text.push_str(&trf("Шифр: {}", &[&exercise.key.title()]));
+    text.push('\n');
+    match (&exercise.key, give_key) {
+        (key, true) => {
+            text.push_str(&describe_key(key));
+            text.push('\n');
+        }
+        (Stage::Vigener { key, .. }, false) => {
+            text.push_str(&trf("Длина ключа: {}", &[&key.chars().count()]));
+            text.push('\n');
+        }
+        (Stage::Grille { .. }, false) => {}
+    }
+    text.push('\n');
+    text.push_str(tr("Расшифруйте текст:"));
+    text.push_str("\n\n");
+    text.push_str(exercise.ciphertext.trim_end());
+    text.push('\n');
+    text
+}
+
+pub fn answers_text(config: &ExerciseConfig, exercises: &[Exercise]) -> String {
+    let mut text = trf("Ответы, зерно {}", &[&config.seed]);
+    text.push('\n');
+    for exercise in exercises {
+        text.push('\n');
+        text.push_str(&trf("Вариант {}", &[&exercise.number]));
+        text.push('\n');
+        text.push_str(&describe_key(&exercise.key));
+        text.push('\n');
+        text.push_str(tr("Открытый текст:"));
+        text.push('\n');
+        text.push_str(&exercise.plaintext);
+        text.push('\n');
+    }
+    text
+}
+
+/* В каталоге: variant_NN.txt с заданием, variant_NN.key.toml с ключом для проверки
+ * и общий answers.txt; возвращает число записанных вариантов */
+pub fn write_files(
+    dir: &Path,
+    config: &ExerciseConfig,
+    exercises: &[Exercise],
+) -> Result<usize, CipherError> {
+    std::fs::create_dir_all(dir)?;
+    let width = exercises.len().to_string().len().max(2);
+    for exercise in exercises {
+        let stem = format!("variant_{:0width$}", exercise.number);
+        std::fs::write(
+            dir.join(format!("{stem}.txt")),
+            task_text(exercise, config.give_key),
+        )?;
+        let file = KeyFile {
+            comment: trf("Вариант {}, зерно {}", &[&exercise.number, &config.seed]),
+            key: exercise.key.clone(),
+        };
+        std::fs::write(dir.join(format!("{stem}.key.toml")), file.to_toml())?;
+    }
+    std::fs::write(dir.join("answers.txt"), answers_text(config, exercises))?;
+    Ok(exercises.len())
+}
+
+/* Окно генератора: параметры сохраняются между запусками, загруженный корпус — нет */
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExercisesWindow {
+    config: ExerciseConfig,
+    #[serde(skip)]
+    corpus: Option<(String, String)>,
+    #[serde(skip)]
+    corpus_dialog: ImNativeFileDialog<Option<PathBuf>>,
+    #[serde(skip)]
+    dir_dialog: ImNativeFileDialog<Option<PathBuf>>,
+}
+
+impl ExercisesWindow {
+    fn poll_dialogs(&mut self, notifications: &mut Notifications) {
+        match self.corpus_dialog.check() {
+            Some(Ok(Some(path))) => match std::fs::read(&path) {
+                Ok(bytes) => {
+                    self.corpus = Some((path.display().to_string(), decode_auto(&bytes).text));
+                }
+                Err(e) => {
+                    notifications.error(trf("Не удалось прочитать {}: {}", &[&path.display(), &e]))
+                }
+            },
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
+        match self.dir_dialog.check() {
+            Some(Ok(Some(dir))) => {
+                let corpus = self.corpus.as_ref().map(|(_, text)| text.as_str());
+                let written = generate(&self.config, corpus)
+                    .and_then(|exercises| write_files(&dir, &self.config, &exercises));
+                match written {
+                    Ok(count) => notifications
+                        .info(trf("Создано вариантов: {} в {}", &[&count, &dir.display()])),
+                    Err(e) => notifications.error(trf("Не удалось создать задания: {}", &[&e])),
+                }
+            }
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, notifications: &mut Notifications) {
+        self.poll_dialogs(notifications);
+        let config = &mut self.config;
+
+        egui::Grid::new("exercises_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label(tr("Шифр:"));
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut config.cipher, ExerciseCipher::Vigener, tr("Виженер"));
+                    ui.radio_value(
+                        &mut config.cipher,
+                        ExerciseCipher::Grille,
+                        tr("Вращающаяся решётка"),
+                    );
+                });
+                ui.end_row();
+
+                match config.cipher {
+                    ExerciseCipher::Vigener => {
+                        ui.label(tr("Длина ключа:"));
+                        ui.add(
+                            egui::DragValue::new(&mut config.key_length)
+                                .clamp_range(1..=MAX_KEY_LENGTH),
+                        );
+                    }
+                    ExerciseCipher::Grille => {
+                        ui.label(tr("Язык текста:"));
+                        egui::ComboBox::from_id_source("exercise_language")
+                            .selected_text(config.language.name())
+                            .show_ui(ui, |ui| {
+                                for language in LANGUAGES {
+                                    ui.selectable_value(
+                                        &mut config.language,
+                                        language,
+                                        language.name(),
+                                    );
+                                }
+                            });
+                    }
+                }
+                ui.end_row();
+
+                ui.label(tr("Открытый текст:"));
+                ui.horizontal(|ui| {
+                    ui.radio_value(
+                        &mut config.source,
+                        TextSource::Corpus,
+                        tr("Отрывок корпуса"),
+                    );
+                    ui.radio_value(
+                        &mut config.source,
+                        TextSource::Random,
+                        tr("Случайные буквы"),
+                    );
+                });
+                ui.end_row();
+
+                if config.source == TextSource::Corpus {
+                    ui.label(tr("Корпус:"));
+                    ui.horizontal(|ui| {
+                        match &self.corpus {
+                            Some((path, _)) => ui.label(path),
+                            None => ui.label(tr("встроенный")),
+                        };
+                        if ui.button(tr("Загрузить...")).clicked() {
+                            if let Err(e) = self.corpus_dialog.open_single_file(None) {
+                                notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                            }
+                        }
+                        if self.corpus.is_some() && ui.button(tr("Встроенный")).clicked()
+                        {
+                            self.corpus = None;
+                        }
+                    });
+                    ui.end_row();
+                }
+
+                ui.label(tr("Букв в тексте:"));
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut config.letters).clamp_range(1..=MAX_LETTERS));
+                    if config.text_letters() != config.letters {
+                        ui.label(trf("(до целых блоков: {})", &[&config.text_letters()]));
+                    }
+                });
+                ui.end_row();
+
+                ui.label(tr("Вариантов:"));
+                ui.add(egui::DragValue::new(&mut config.count).clamp_range(1..=MAX_COUNT));
+                ui.end_row();
+
+                ui.label(tr("Зерно:"));
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut config.seed));
+                    if ui
+                        .button(tr("Случайное"))
+                        .on_hover_text(tr("С тем же зерном получатся те же варианты"))
+                        .clicked()
+                    {
+                        config.seed = rand::thread_rng().gen_range(0..1_000_000);
+                    }
+                });
+                ui.end_row();
+            });
+        ui.checkbox(&mut config.give_key, tr("Указывать ключ в задании"));
+
+        if ui.button(tr("Создать задания в папке...")).clicked() {
+            if let Err(e) = self.dir_dialog.open_single_dir(None) {
+                notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotating_grille::{grille_letters, Grille};
+
+    #[test]
+    fn same_seed_gives_same_exercises() {
+        let config = ExerciseConfig::default();
+        let first = generate(&config, None).unwrap();
+        assert_eq!(first, generate(&config, None).unwrap());
+        assert_eq!(first.len(), 20);
+        let other = ExerciseConfig {
+            seed: 2,
+            ..config.clone()
+        };
+        assert_ne!(first, generate(&other, None).unwrap());
+        for exercise in &first {
+            assert_eq!(filter_russian(exercise.plaintext.chars()).count(), 200);
+            assert_eq!(exercise.key.decrypt(&exercise.ciphertext).unwrap(), {
+                filter_russian(exercise.plaintext.chars()).collect::<String>()
+            });
+        }
+    }
+
+    #[test]
+    fn grille_texts_fill_whole_blocks() {
+        let config = ExerciseConfig {
+            cipher: ExerciseCipher::Grille,
+            language: Language::English,
+            letters: 50,
+            count: 5,
+            ..Default::default()
+        };
+        for source in [TextSource::Corpus, TextSource::Random] {
+            let config = ExerciseConfig {
+                source,
+                ..config.clone()
+            };
+            for exercise in generate(&config, None).unwrap() {
+                assert_eq!(grille_letters(&exercise.plaintext).count(), 64);
+                let Stage::Grille { key } = exercise.key else {
+                    panic!("ожидалась решётка");
+                };
+                let decrypted = Grille::new(key)
+                    .unwrap()
+                    .decrypt_text(&exercise.ciphertext)
+                    .unwrap();
+                assert_eq!(
+                    decrypted,
+                    grille_letters(&exercise.plaintext).collect::<String>()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn seed_gives_the_same_exercises_everywhere() {
+        /* Номер варианта, напечатанный год назад, должен давать тот же ключ и текст */
+        let vigener = &generate(&ExerciseConfig::default(), None).unwrap()[0];
+        let Stage::Vigener { key, .. } = &vigener.key else {
+            panic!("ожидался Виженер");
+        };
+        assert_eq!(key, "МНЖЧЫ");
+        assert!(vigener
+            .plaintext
+            .starts_with("тайных шифрах. Он начал с того, что написал"));
+        assert!(vigener.ciphertext.starts_with("ЯНРЕЦГЖРММ"));
+
+        let config = ExerciseConfig {
+            cipher: ExerciseCipher::Grille,
+            ..Default::default()
+        };
+        let grille = &generate(&config, None).unwrap()[0];
+        let Stage::Grille { key } = grille.key else {
+            panic!("ожидалась решётка");
+        };
+        let holes: Vec<usize> = (0..16).filter(|i| key[i / 4][i % 4]).collect();
+        assert_eq!(holes, [2, 3, 8, 10]);
+        assert!(grille.plaintext.starts_with("со следующей. Со временем"));
+    }
+
+    #[test]
+    fn passages_start_at_words_and_wrap() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let text = passage("раз, два!\nтри", 20, char::is_alphabetic, &mut rng).unwrap();
+        assert!(text.starts_with("раз") || text.starts_with("два") || text.starts_with("три"));
+        assert_eq!(text.chars().filter(|c| c.is_alphabetic()).count(), 20);
+        assert!(!text.contains('\n'));
+        assert!(matches!(
+            generate(&ExerciseConfig::default(), Some("only latin")),
+            Err(CipherError::EmptyCorpus)
+        ));
+    }
+
+    #[test]
+    fn files_hide_key_when_asked() {
+        let config = ExerciseConfig {
+            give_key: false,
+            count: 1,
+            ..Default::default()
+        };
+        let exercises = generate(&config, None).unwrap();
+        let Stage::Vigener { key, .. } = &exercises[0].key else {
+            panic!("ожидался Виженер");
+        };
+        let task = task_text(&exercises[0], false);
+        assert!(!task.contains(key.as_str()));
+        assert!(task.contains(&exercises[0].ciphertext));
+        assert!(answers_text(&config, &exercises).contains(key.as_str()));
+        assert!(task_text(&exercises[0], true).contains(key.as_str()));
+    }
+}
@@ -0,0 +1,209 @@
+/* Атака на вращающуюся решётку по одному шифртексту: у решётки 4x4 всего 4^4 = 256
+ * правильных ключей, поэтому перебираются все, а расшифровки оцениваются языковой моделью
+ * языка шифртекста. Программа работает только с решётками 4x4; для больших решёток
+ * полный перебор невозможен и понадобился бы отжиг или восхождение к вершине */
+
+use crate::grille_key::key_preview;
+use crate::i18n::{tr, trf};
+use crate::language_model::{model, Language, LanguageModel};
+use crate::rotating_grille::*;
+use crate::task::Task;
+use eframe::egui;
+
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub key: CardboardMatrix,
+    pub score: f64,
+    pub plaintext: String,
+}
+
+/* Ищет ключ, при котором расшифровка больше всего похожа на язык модели.
+ * on_progress получает долю выполненной работы и лучший кандидат, возвращает true для остановки. */
+pub fn search(
+    ciphertext: &str,
+    model: &LanguageModel,
+    mut on_progress: impl FnMut(f32, &Candidate) -> bool,
+) -> Option<Candidate> {
+    if grille_letters(ciphertext).count() < 16 {
+        return None;
+    }
+
+    let total = 4usize.pow(4);
+    let mut best: Option<Candidate> = None;
+    for (index, key) in all_keys().enumerate() {
+        let Ok(grille) = Grille::new(key) else {
+            continue;
+        };
+        let plaintext = grille.decrypt_blocks(ciphertext);
+        let score = model.score(&plaintext);
+        if best.as_ref().is_none_or(|b| score > b.score) {
+            best = Some(Candidate {
+                key,
+                score,
+                plaintext,
+            });
+        }
+
+        if index % 16 == 15 {
+            if let Some(best) = &best {
+                if on_progress((index + 1) as f32 / total as f32, best) {
+                    break;
+                }
+            }
+        }
+    }
+
+    best
+}
+
+pub struct AttackProgress {
+    pub fraction: f32,
+    pub best: Candidate,
+}
+
+#[derive(Default)]
+pub struct AttackPanel {
+    task: Task<AttackProgress, Option<Candidate>>,
+    result: Option<Candidate>,
+}
+
+impl AttackPanel {
+    pub fn result(&self) -> Option<&Candidate> {
+        self.result.as_ref()
+    }
+
+    /* Возвращает true, если пользователь применил найденный ключ */
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        ciphertext: &str,
+        key: &mut CardboardMatrix,
+        plaintext: &mut String,
+    ) -> bool {
+        if let Some(result) = self.task.poll(ui.ctx()) {
+            self.result = result;
+        }
+
+        if self.task.is_running() {
+            ui.horizontal(|ui| {
+                let fraction = self.task.progress.as_ref().map_or(0.0, |p| p.fraction);
+                ui.add(egui::ProgressBar::new(fraction).show_percentage());
+                if ui.button(tr("Отмена")).clicked() {
+                    self.task.cancel();
+                }
+            });
+            if let Some(progress) = &self.task.progress {
+                ui.label(trf(
+                    "Лучшая оценка: {}",
+                    &[&format!("{:.1}", progress.best.score)],
+                ));
+                ui.label(&progress.best.plaintext);
+            }
+        } else {
+            let enough = grille_letters(ciphertext).count() >= 16;
+            if ui
+                .add_enabled(enough, egui::Button::new(tr("Подобрать ключ")))
+                .on_disabled_hover_text(tr("Нужен хотя бы один полный блок шифртекста (16 букв)"))
+                .clicked()
+            {
+                let ciphertext = ciphertext.to_owned();
+                let model = model(Language::of_text(&ciphertext));
+                self.result = None;
+                self.task.spawn(move |reporter| {
+                    search(&ciphertext, &model, |fraction, best| {
+                        reporter.progress(AttackProgress {
+                            fraction,
+                            best: best.clone(),
+                        });
+                        reporter.is_cancelled()
+                    })
+                });
+            }
+        }
+
+        let mut applied = false;
+        if let Some(result) = &self.result {
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.monospace(key_preview(&result.key));
+                ui.vertical(|ui| {
+                    ui.label(trf("Оценка: {}", &[&format!("{:.1}", result.score)]));
+                    if ui.button(tr("Применить ключ")).clicked() {
+                        *key = result.key;
+                        *plaintext = result.plaintext.clone();
+                        applied = true;
+                    }
+                });
+            });
+            ui.label(&result.plaintext);
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAINTEXT: &str = "ANOTHERSIMPLEEXAMPLEOFTHEMETHODISTOWRITETHEWORDSOFAMESSAGEINTOTHEHOLESOFTHECARDANDTHENTURNITAROUNDUNTILTHESQUAREISFULLOFLETTERSX";
+
+    #[test]
+    fn recovers_key_of_english_text() {
+        let key = [
+            [false, false, false, true],
+            [false, false, true, false],
+            [true, false, false, false],
+            [false, true, false, false],
+        ];
+        assert!(is_valid_key(&key));
+        assert_eq!(PLAINTEXT.len() % 16, 0);
+        let ciphertext = Grille::new(key).unwrap().encrypt_text(PLAINTEXT);
+
+        let mut reports = Vec::new();
+        let found = search(&ciphertext, &model(Language::English), |fraction, _| {
+            reports.push(fraction);
+            false
+        })
+        .unwrap();
+        assert_eq!(found.key, key);
+        assert_eq!(found.plaintext, PLAINTEXT);
+        assert_eq!(reports.len(), 16);
+        assert_eq!(reports.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn russian_ciphertext_is_scored_as_russian() {
+        let ciphertext = Grille::new(key_from_choices(&[1, 2, 3, 0]))
+            .unwrap()
+            .encrypt_text(PLAINTEXT);
+        assert_eq!(Language::of_text(&ciphertext), Language::English);
+        assert_eq!(Language::of_text("ЁЖИК В ТУМАНЕ, 1975"), Language::Russian);
+
+        let russian = "ПРИШИФРОВАНИИРЕШЁТКОЙБУКВЫНЕМЕНЯЮТСЯАЛИШЬПЕРЕСТАВЛЯЮТСЯПОКВАДРАТУ";
+        let key = key_from_choices(&[3, 1, 0, 2]);
+        let ciphertext = Grille::new(key).unwrap().encrypt_text(russian);
+        let found = search(
+            &ciphertext,
+            &model(Language::of_text(&ciphertext)),
+            |_, _| false,
+        );
+        assert_eq!(found.unwrap().key, key);
+    }
+
+    #[test]
+    fn stops_on_request_and_needs_a_full_block() {
+        let model = model(Language::English);
+        assert!(search("ABCDEFGHIJKLMNO", &model, |_, _| false).is_none());
+
+        let ciphertext = Grille::new(key_from_choices(&[1, 2, 3, 0]))
+            .unwrap()
+            .encrypt_text(PLAINTEXT);
+        let mut calls = 0;
+        let found = search(&ciphertext, &model, |_, _| {
+            calls += 1;
+            true
+        });
+        assert_eq!(calls, 1);
+        assert!(found.is_some());
+    }
+}
@@ -0,0 +1,249 @@
+/* Редактор ключа вращающейся решётки: мышь, клавиатура и экранный диктор */
+
+use crate::i18n::{tr, trf};
+use crate::rotating_grille::*;
+use eframe::egui;
+use egui::{Color32, Key, Pos2, Rect, Rounding, Sense, Stroke, Vec2, WidgetInfo, WidgetType};
+
+const MIN_SIDE: f32 = 60.0;
+const MAX_SIDE: f32 = 320.0;
+
+/* Цвета орбит (палитра Окабэ–Ито, различима при дальтонизме) */
+const ORBIT_COLORS: [Color32; 4] = [
+    Color32::from_rgb(230, 159, 0),
+    Color32::from_rgb(86, 180, 233),
+    Color32::from_rgb(0, 158, 115),
+    Color32::from_rgb(204, 121, 167),
+];
+
+pub struct GrilleKeyEditor<'a> {
+    key: &'a mut CardboardMatrix,
+}
+
+impl<'a> GrilleKeyEditor<'a> {
+    pub fn new(key: &'a mut CardboardMatrix) -> Self {
+        GrilleKeyEditor { key }
+    }
+}
+
+fn describe(key: &CardboardMatrix, (y, x): (usize, usize)) -> String {
+    let state = if key[y][x] {
+        tr("отверстие")
+    } else if is_blocked(key, y, x) {
+        tr("закрыта поворотом")
+    } else {
+        tr("пусто")
+    };
+    trf(
+        "Ключ решётки, отверстий {} из 4. Строка {}, столбец {}: {}",
+        &[&hole_count(key), &(y + 1), &(x + 1), &state],
+    )
+}
+
+impl egui::Widget for GrilleKeyEditor<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let key = self.key;
+
+        let available = ui.available_size();
+        let side = if available.y.is_finite() && available.y >= MIN_SIDE {
+            available.x.min(available.y)
+        } else {
+            available.x
+        };
+        let side = side.clamp(MIN_SIDE, MAX_SIDE);
+        let (rect, mut response) = ui.allocate_exact_size(Vec2::splat(side), Sense::click());
+        let id = response.id;
+
+        let cell_step = side / 4.0;
+        let cell_at = |pos: Pos2| {
+            let local = (pos - rect.min) / cell_step;
+            let (x, y) = (local.x.floor(), local.y.floor());
+            let inside = (0.0..4.0).contains(&x) && (0.0..4.0).contains(&y);
+            inside.then_some((y as usize, x as usize))
+        };
+
+        let old_cursor: (usize, usize) = ui.data_mut(|d| *d.get_temp_mut_or_default(id));
+        let mut cursor = old_cursor;
+        let mut toggle = None;
+
+        if response.clicked() {
+            response.request_focus();
+            if let Some(cell) = response.interact_pointer_pos().and_then(cell_at) {
+                cursor = cell;
+                toggle = Some(cell);
+            }
+        }
+
+        let has_focus = response.has_focus();
+        if has_focus {
+            ui.input(|i| {
+                let (y, x) = &mut cursor;
+                if i.key_pressed(Key::ArrowLeft) {
+                    *x = (*x + 3) % 4;
+                }
+                if i.key_pressed(Key::ArrowRight) {
+                    *x = (*x + 1) % 4;
+                }
+                if i.key_pressed(Key::ArrowUp) {
+                    *y = (*y + 3) % 4;
+                }
+                if i.key_pressed(Key::ArrowDown) {
+                    *y = (*y + 1) % 4;
+                }
+                if i.key_pressed(Key::Space) || i.key_pressed(Key::Enter) {
+                    toggle = Some(cursor);
+                }
+            });
+        }
+        ui.data_mut(|d| d.insert_temp(id, cursor));
+
+        if let Some((y, x)) = toggle {
+            if key[y][x] || !is_blocked(key, y, x) {
+                key[y][x] = !key[y][x];
+                response.mark_changed();
+            }
+        }
+
+        /* Под курсором мыши (или клавиатурным курсором) показываем орбиту клетки */
+        let preview = response
+            .hover_pos()
+            .and_then(cell_at)
+            .or(has_focus.then_some(cursor));
+
+        let visuals = ui.visuals();
+        let color = visuals.strong_text_color();
+        let disabled_color = visuals.widgets.inactive.bg_fill;
+        let gap = (cell_step * 0.12).max(2.0);
+        let cell_rect = |(y, x): (usize, usize)| {
+            Rect::from_min_size(
+                rect.min + Vec2::new(x as f32, y as f32) * cell_step + Vec2::splat(gap / 2.0),
+                Vec2::splat(cell_step - gap),
+            )
+        };
+
+        let holes = orbit_holes(key);
+        let painter = ui.painter_at(rect);
+        for y in 0..4 {
+            for x in 0..4 {
+                let cell = cell_rect((y, x));
+                let orbit = orbit_index(y, x);
+                let orbit_color = ORBIT_COLORS[orbit];
+
+                if key[y][x] {
+                    painter.rect_filled(cell, Rounding::default(), color);
+                } else if is_blocked(key, y, x) {
+                    painter.rect_filled(cell, Rounding::default(), disabled_color);
+                    painter.rect_filled(
+                        cell.shrink(gap),
+                        Rounding::default(),
+                        orbit_color.linear_multiply(0.25),
+                    );
+                } else {
+                    painter.rect_filled(
+                        cell,
+                        Rounding::default(),
+                        orbit_color.linear_multiply(0.35),
+                    );
+                }
+
+                /* Орбита без отверстия обведена толще, конфликтующие отверстия — красным */
+                let cell_stroke = match holes[orbit] {
+                    0 => Stroke::new(4.0, orbit_color),
+                    1 => Stroke::new(2.0, orbit_color),
+                    _ => Stroke::new(4.0, visuals.error_fg_color),
+                };
+                painter.rect_stroke(cell, Rounding::default(), cell_stroke);
+                if key[y][x] && holes[orbit] > 1 {
+                    painter.text(
+                        cell.center(),
+                        egui::Align2::CENTER_CENTER,
+                        "×",
+                        egui::FontId::proportional(cell_step * 0.5),
+                        visuals.error_fg_color,
+                    );
+                }
+            }
+        }
+
+        if let Some((y, x)) = preview {
+            let preview_stroke = Stroke::new(2.0, visuals.selection.bg_fill);
+            for (i, cell) in orbit(y, x).into_iter().enumerate().skip(1) {
+                let cell = cell_rect(cell).shrink(gap);
+                painter.rect_stroke(cell, Rounding::same(gap), preview_stroke);
+                painter.text(
+                    cell.center(),
+                    egui::Align2::CENTER_CENTER,
+                    i.to_string(),
+                    egui::FontId::proportional(cell_step * 0.3),
+                    visuals.selection.bg_fill,
+                );
+            }
+        }
+
+        if has_focus {
+            painter.rect_stroke(
+                cell_rect(cursor).expand(gap / 2.0 - 1.0),
+                Rounding::default(),
+                visuals.selection.stroke,
+            );
+        }
+
+        response.widget_info(|| WidgetInfo::labeled(WidgetType::Other, describe(key, cursor)));
+        if cursor != old_cursor {
+            response.output_event(egui::output::OutputEvent::ValueChanged(
+                WidgetInfo::labeled(WidgetType::Other, describe(key, cursor)),
+            ));
+        }
+        response.on_hover_text(tr(
+            "Щелчок или пробел — вырезать отверстие, стрелки — перемещение",
+        ))
+    }
+}
+
+/* Легенда под редактором: состояние каждой орбиты и сколько отверстий осталось */
+pub fn grille_key_hints(ui: &mut egui::Ui, key: &CardboardMatrix) {
+    let holes = orbit_holes(key);
+    let missing = holes.iter().filter(|h| **h == 0).count();
+
+    ui.horizontal_wrapped(|ui| {
+        for (orbit, &count) in holes.iter().enumerate() {
+            let (rect, _) = ui.allocate_exact_size(Vec2::splat(12.0), Sense::hover());
+            ui.painter()
+                .rect_filled(rect, Rounding::default(), ORBIT_COLORS[orbit]);
+            let status = match count {
+                0 => tr("нужно отверстие").to_owned(),
+                1 => tr("готово").to_owned(),
+                n => trf("конфликт: {} отверстия", &[&n]),
+            };
+            let text = egui::RichText::new(status);
+            ui.label(if count > 1 {
+                text.color(ui.visuals().error_fg_color)
+            } else {
+                text
+            });
+        }
+    });
+
+    if is_valid_key(key) {
+        ui.label(tr("Ключ готов"));
+    } else if holes.iter().any(|h| *h > 1) {
+        ui.colored_label(
+            ui.visuals().error_fg_color,
+            tr("В одной орбите не может быть двух отверстий: при повороте они совпадут"),
+        );
+    } else {
+        ui.label(trf("Осталось вырезать: {}", &[&missing]));
+    }
+}
+
+/* Текстовый эскиз ключа для списков кандидатов */
+pub fn key_preview(key: &CardboardMatrix) -> String {
+    key.iter()
+        .map(|row| {
+            row.iter()
+                .map(|&h| if h { '■' } else { '□' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
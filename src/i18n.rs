@@ -0,0 +1,166 @@
+/* Перевод интерфейса. Исходные строки в коде на русском и служат ключами каталогов,
+ * как msgid в gettext; каталог другого языка сопоставляет им перевод */
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+use toml_edit::Document;
+
+const ENGLISH_CATALOG: &str = include_str!("../locale/en.toml");
+
+/* Язык интерфейса; языки моделей для атак — отдельный список в language_model */
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Locale {
+    Russian,
+    English,
+}
+
+pub const LOCALES: [Locale; 2] = [Locale::Russian, Locale::English];
+
+impl Locale {
+    /* Название языка на нём самом, чтобы переключатель был понятен при любом языке интерфейса */
+    pub fn native_name(self) -> &'static str {
+        match self {
+            Locale::Russian => "Русский",
+            Locale::English => "English",
+        }
+    }
+
+    /* Код языка для атрибута lang в HTML */
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::Russian => "ru",
+            Locale::English => "en",
+        }
+    }
+}
+
+static LOCALE: AtomicU8 = AtomicU8::new(Locale::Russian as u8);
+static ENGLISH: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+pub fn locale() -> Locale {
+    match LOCALE.load(Ordering::Relaxed) {
+        x if x == Locale::English as u8 => Locale::English,
+        _ => Locale::Russian,
+    }
+}
+
+pub fn set_locale(locale: Locale) {
+    LOCALE.store(locale as u8, Ordering::Relaxed);
+}
+
+fn parse_catalog(text: &str) -> HashMap<String, String> {
+    let doc: Document = text.parse().expect("каталог перевода — корректный TOML");
+    doc.iter()
+        .filter_map(|(key, item)| Some((key.to_owned(), item.as_str()?.to_owned())))
+        .collect()
+}
+
+fn catalog(locale: Locale) -> Option<&'static HashMap<String, String>> {
+    match locale {
+        Locale::Russian => None,
+        Locale::English => Some(ENGLISH.get_or_init(|| parse_catalog(ENGLISH_CATALOG))),
+    }
+}
+
+pub fn translate(locale: Locale, text: &'static str) -> &'static str {
+    catalog(locale)
+        .and_then(|catalog| catalog.get(text))
+        .map_or(text, String::as_str)
+}
+
+/* Строка на текущем языке интерфейса; без перевода остаётся исходная */
+pub fn tr(text: &'static str) -> &'static str {
+    translate(locale(), text)
+}
+
+/* Перевод шаблона с подстановкой аргументов вместо {} по порядку */
+pub fn trf(template: &'static str, args: &[&dyn Display]) -> String {
+    let mut args = args.iter();
+    let mut parts = tr(template).split("{}");
+    let mut text = parts.next().unwrap_or_default().to_owned();
+    for part in parts {
+        if let Some(arg) = args.next() {
+            text.push_str(&arg.to_string());
+        }
+        text.push_str(part);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Строковые литералы в вызовах tr и trf по всем исходникам */
+    fn source_messages() -> Vec<String> {
+        let sources = std::fs::read_dir("src")
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap());
+        let mut messages = Vec::new();
+        for source in sources {
+            for (i, call) in source
+                .match_indices("tr(")
+                .chain(source.match_indices("trf("))
+            {
+                let before = source[..i].chars().next_back().unwrap_or(' ');
+                if before.is_alphanumeric() || before == '_' || before == '"' {
+                    continue;
+                }
+                let rest = source[i + call.len()..].trim_start();
+                let Some(literal) = rest.strip_prefix('"') else {
+                    continue;
+                };
+                let mut message = String::new();
+                let mut chars = literal.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => message.push('\n'),
+                            Some('\n') => {
+                                chars = chars.as_str().trim_start().chars();
+                            }
+                            Some(other) => message.push(other),
+                            None => {}
+                        },
+                        c => message.push(c),
+                    }
+                }
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn english_catalog_is_complete() {
+        let english = parse_catalog(ENGLISH_CATALOG);
+        let messages = source_messages();
+        assert!(messages.len() > 100);
+        for message in &messages {
+            let translation = english.get(message);
+            assert!(translation.is_some(), "нет перевода для «{message}»");
+            assert_eq!(
+                translation.unwrap().matches("{}").count(),
+                message.matches("{}").count(),
+                "{message}"
+            );
+        }
+        for key in english.keys() {
+            assert!(messages.contains(key), "лишний перевод «{key}»");
+        }
+    }
+
+    #[test]
+    fn templates_are_filled_in_order() {
+        assert_eq!(translate(Locale::English, "Ключ готов"), "Key is ready");
+        assert_eq!(translate(Locale::Russian, "Ключ готов"), "Ключ готов");
+        assert_eq!(
+            trf("Осталось вырезать: {}", &[&3]),
+            tr("Осталось вырезать: {}").replace("{}", "3")
+        );
+    }
+}
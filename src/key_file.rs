@@ -0,0 +1,264 @@
+/* Файлы ключей в формате TOML: шифр, алфавит, параметры варианта и сам ключ */
+
+use crate::error::CipherError;
+use crate::i18n::{tr, trf};
+use crate::notifications::Notifications;
+use crate::pipeline::Stage;
+use crate::rotating_grille::key_rows;
+use eframe::egui;
+use im_native_dialog::ImNativeFileDialog;
+use std::io;
+use std::mem::discriminant;
+use std::path::{Path, PathBuf};
+use toml_edit::{value, Array, Document, Item};
+
+/* Пример файла:
+ *
+ * cipher = "vigener"
+ * alphabet = "russian"
+ * comment = "Лабораторная 1, вариант 3"
+ *
+ * [vigener]
+ * progressive = true
+ * key = "КЛЮЧ"
+ * alphabet_key = ""
+ *
+ * Для решётки — cipher = "grille", alphabet = "any" и таблица
+ * [grille] с size = 4 и key = ["...#", "..#.", "#...", ".#.."] */
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyFile {
+    pub comment: String,
+    pub key: Stage,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn string<'a>(item: &'a Item, name: &str) -> io::Result<&'a str> {
+    item.get(name)
+        .and_then(Item::as_str)
+        .ok_or_else(|| invalid(format!("нет строкового поля {name}")))
+}
+
+impl KeyFile {
+    pub fn to_toml(&self) -> String {
+        let mut doc = Document::new();
+        match &self.key {
+            Stage::Vigener { key, alphabet_key } => {
+                doc["cipher"] = value("vigener");
+                doc["alphabet"] = value("russian");
+                doc["comment"] = value(self.comment.as_str());
+                doc["vigener"] = toml_edit::table();
+                doc["vigener"]["progressive"] = value(true);
+                doc["vigener"]["key"] = value(key.as_str());
+                doc["vigener"]["alphabet_key"] = value(alphabet_key.as_str());
+            }
+            Stage::Grille { key } => {
+                doc["cipher"] = value("grille");
+                doc["alphabet"] = value("any");
+                doc["comment"] = value(self.comment.as_str());
+                doc["grille"] = toml_edit::table();
+                doc["grille"]["size"] = value(4);
+                doc["grille"]["key"] = value(Array::from_iter(key_rows::to_rows(key)));
+            }
+        }
+        doc.to_string()
+    }
+
+    pub fn from_toml(text: &str) -> io::Result<Self> {
+        let doc: Document = text
+            .parse()
+            .map_err(|e: toml_edit::TomlError| invalid(e.to_string()))?;
+        let root = doc.as_item();
+        let comment = root
+            .get("comment")
+            .and_then(Item::as_str)
+            .unwrap_or_default()
+            .to_owned();
+
+        let key = match string(root, "cipher")? {
+            "vigener" => {
+                if string(root, "alphabet")? != "russian" {
+                    return Err(invalid("Виженер поддерживает только русский алфавит"));
+                }
+                let section = root
+                    .get("vigener")
+                    .ok_or_else(|| invalid("нет таблицы [vigener]"))?;
+                if section.get("progressive").and_then(Item::as_bool) == Some(false) {
+                    return Err(invalid("поддерживается только прогрессивный Виженер"));
+                }
+                let alphabet_key = section
+                    .get("alphabet_key")
+                    .and_then(Item::as_str)
+                    .unwrap_or_default();
+                Stage::Vigener {
+                    key: string(section, "key")?.to_owned(),
+                    alphabet_key: alphabet_key.to_owned(),
+                }
+            }
+            "grille" => {
+                let section = root
+                    .get("grille")
+                    .ok_or_else(|| invalid("нет таблицы [grille]"))?;
+                if section.get("size").and_then(Item::as_integer).unwrap_or(4) != 4 {
+                    return Err(invalid("поддерживается только решётка 4×4"));
+                }
+                let rows: Vec<String> = section
+                    .get("key")
+                    .and_then(Item::as_array)
+                    .ok_or_else(|| invalid("нет массива строк key"))?
+                    .iter()
+                    .map(|row| row.as_str().unwrap_or_default().to_owned())
+                    .collect();
+                let key = key_rows::from_rows(&rows).map_err(|e| {
+                    invalid(format!(
+                        "ключ решётки — 4 строки по 4 клетки из '#' и '.' ({e})"
+                    ))
+                })?;
+                Stage::Grille { key }
+            }
+            other => return Err(invalid(format!("неизвестный шифр {other}"))),
+        };
+        Ok(KeyFile { comment, key })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CipherError> {
+        Ok(Self::from_toml(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CipherError> {
+        Ok(std::fs::write(path, self.to_toml())?)
+    }
+}
+
+/* Кнопки «Ключ из файла» и «Сохранить ключ» для вкладки */
+#[derive(Default)]
+pub struct KeyFileButtons {
+    read_dialog: ImNativeFileDialog<Option<PathBuf>>,
+    write_dialog: ImNativeFileDialog<Option<PathBuf>>,
+    /* Ключ, который сохраняется после выбора файла */
+    pending: Option<KeyFile>,
+}
+
+impl KeyFileButtons {
+    /* Возвращает ключ из файла, если он подходит к шифру вкладки */
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        current: Stage,
+        comment: &str,
+        notifications: &mut Notifications,
+    ) -> Option<Stage> {
+        let mut loaded = None;
+
+        match self.read_dialog.check() {
+            Some(Ok(Some(path))) => match KeyFile::load(&path) {
+                Ok(file) if discriminant(&file.key) != discriminant(&current) => {
+                    notifications.error(trf(
+                        "{}: ключ для шифра «{}», а не «{}»",
+                        &[&path.display(), &file.key.title(), &current.title()],
+                    ));
+                }
+                Ok(file) => {
+                    let mut message = trf("Загружен ключ {}", &[&path.display()]);
+                    if !file.comment.is_empty() {
+                        message = format!("{message}: {}", file.comment);
+                    }
+                    notifications.info(message);
+                    loaded = Some(file.key);
+                }
+                Err(e) => {
+                    notifications.error(trf("Не удалось загрузить {}: {}", &[&path.display(), &e]))
+                }
+            },
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
+
+        match self.write_dialog.check() {
+            Some(Ok(Some(path))) => {
+                if let Some(file) = self.pending.take() {
+                    match file.save(&path) {
+                        Ok(()) => notifications.info(trf("Ключ сохранён в {}", &[&path.display()])),
+                        Err(e) => notifications
+                            .error(trf("Не удалось записать {}: {}", &[&path.display(), &e])),
+                    }
+                }
+            }
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
+
+        if ui.button(tr("Ключ из файла...")).clicked() {
+            if let Err(e) = self.read_dialog.open_single_file(None) {
+                notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+            }
+        }
+        if ui.button(tr("Сохранить ключ...")).clicked() {
+            self.pending = Some(KeyFile {
+                comment: comment.to_owned(),
+                key: current,
+            });
+            if let Err(e) = self.write_dialog.show_save_single_file(None) {
+                notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+            }
+        }
+        loaded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotating_grille::key_from_choices;
+
+    #[test]
+    fn round_trip_both_ciphers() {
+        for key in [
+            Stage::Vigener {
+                key: "КЛЮЧ".to_owned(),
+                alphabet_key: "ЗАМОК".to_owned(),
+            },
+            Stage::Grille {
+                key: key_from_choices(&[2, 0, 3, 1]),
+            },
+        ] {
+            let file = KeyFile {
+                comment: "Лабораторная 1, \"вариант\" 3".to_owned(),
+                key,
+            };
+            assert_eq!(KeyFile::from_toml(&file.to_toml()).unwrap(), file);
+        }
+    }
+
+    #[test]
+    fn reads_handwritten_file() {
+        let file = KeyFile::from_toml(
+            "cipher = \"grille\"\n\
+             alphabet = \"any\"\n\
+             [grille]\n\
+             key = [\"...#\", \"..#.\", \"#...\", \".#..\"]\n",
+        )
+        .unwrap();
+        assert_eq!(file.comment, "");
+        let Stage::Grille { key } = file.key else {
+            panic!("ожидалась решётка");
+        };
+        assert!(key[0][3] && key[1][2] && key[2][0] && key[3][1]);
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        for text in [
+            "cipher = \"enigma\"",
+            "cipher = \"vigener\"\nalphabet = \"latin\"\n[vigener]\nkey = \"KEY\"",
+            "cipher = \"vigener\"\nalphabet = \"russian\"\n[vigener]\nprogressive = false\nkey = \"К\"",
+            "cipher = \"grille\"\n[grille]\nsize = 6\nkey = []",
+            "cipher = \"grille\"\n[grille]\nkey = [\"#\"]",
+            "not toml at all = = =",
+        ] {
+            assert!(KeyFile::from_toml(text).is_err(), "{text}");
+        }
+    }
+}
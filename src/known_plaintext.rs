@@ -0,0 +1,360 @@
+/* Восстановление ключа по известному открытому тексту или его фрагменту */
+
+use crate::grille_key::key_preview;
+use crate::i18n::{tr, trf};
+use crate::notifications::Notifications;
+use crate::rotating_grille::*;
+use crate::vigener_progressive::*;
+use eframe::egui;
+
+/* Больше совпадений фрагмента всё равно не имеет смысла показывать */
+const MAX_MATCHES: usize = 50;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VigenerMatch {
+    /* Позиция фрагмента в шифртексте, в буквах */
+    pub offset: usize,
+    pub key: String,
+}
+
+/* Сдвиг на позиции i равен key[i % period] + i / period, отсюда буква ключа */
+fn vigener_key_for_period(
+    shifts: &[u32],
+    offset: usize,
+    period: usize,
+    alphabet: &Alphabet,
+) -> Option<String> {
+    let len = ALPHABET.len() as u32;
+    let mut key = vec![None; period];
+    for (n, &shift) in shifts.iter().enumerate() {
+        let i = offset + n;
+        let letter = (shift + len - (i / period) as u32 % len) % len;
+        match key[i % period] {
+            None => key[i % period] = Some(letter),
+            Some(known) if known != letter => return None,
+            Some(_) => {}
+        }
+    }
+    key.into_iter()
+        .map(|letter| letter.map(|letter| alphabet.letter(letter)))
+        .collect()
+}
+
+fn vigener_shifts(plaintext: &[u32], ciphertext: &[u32]) -> Vec<u32> {
+    let len = ALPHABET.len() as u32;
+    plaintext
+        .iter()
+        .zip(ciphertext)
+        .map(|(p, c)| (c + len - p) % len)
+        .collect()
+}
+
+/* Номера букв в алфавите таблицы, в котором считаются сдвиги */
+fn russian_letters(text: &str, alphabet: &Alphabet) -> Vec<u32> {
+    filter_russian(text.chars())
+        .filter_map(|c| alphabet.index(c).ok())
+        .collect()
+}
+
+/* Все ключи, переводящие начало открытого текста в начало шифртекста, от коротких к длинным.
+ * Ключ длиной не меньше известного текста проверить нечем, поэтому такие не выдаются. */
+pub fn vigener_keys(plaintext: &str, ciphertext: &str, alphabet: &Alphabet) -> Vec<String> {
+    let plaintext = russian_letters(plaintext, alphabet);
+    let ciphertext = russian_letters(ciphertext, alphabet);
+    let shifts = vigener_shifts(&plaintext, &ciphertext);
+
+    (1..shifts.len())
+        .filter_map(|period| vigener_key_for_period(&shifts, 0, period, alphabet))
+        .collect()
+}
+
+/* Ищет фрагмент открытого текста во всех позициях шифртекста.
+ * Для каждой позиции выдаётся самый короткий согласованный ключ не длиннее max_period. */
+pub fn vigener_crib(
+    crib: &str,
+    ciphertext: &str,
+    max_period: usize,
+    alphabet: &Alphabet,
+) -> Vec<VigenerMatch> {
+    let crib = russian_letters(crib, alphabet);
+    let ciphertext = russian_letters(ciphertext, alphabet);
+    if crib.len() < 2 || crib.len() > ciphertext.len() {
+        return Vec::new();
+    }
+
+    (0..=ciphertext.len() - crib.len())
+        .filter_map(|offset| {
+            let shifts = vigener_shifts(&crib, &ciphertext[offset..]);
+            (1..crib.len().min(max_period + 1)).find_map(|period| {
+                let key = vigener_key_for_period(&shifts, offset, period, alphabet)?;
+                Some(VigenerMatch { offset, key })
+            })
+        })
+        .take(MAX_MATCHES)
+        .collect()
+}
+
+/* Все правильные ключи решётки, при которых шифртекст расшифровывается в начало открытого текста */
+pub fn grille_keys(plaintext: &str, ciphertext: &str) -> Vec<CardboardMatrix> {
+    let plaintext: String = grille_letters(plaintext).collect();
+    all_keys()
+        .filter(|&key| {
+            let Ok(grille) = Grille::new(key) else {
+                return false;
+            };
+            let decrypted = grille.decrypt_blocks(ciphertext);
+            !decrypted.is_empty()
+                && !plaintext.is_empty()
+                && decrypted
+                    .chars()
+                    .zip(plaintext.chars())
+                    .all(|(d, p)| d == p)
+        })
+        .collect()
+}
+
+/* Ключи решётки, при которых фрагмент встречается в расшифровке, с позицией первого вхождения */
+pub fn grille_crib(crib: &str, ciphertext: &str) -> Vec<(CardboardMatrix, usize)> {
+    let crib: String = grille_letters(crib).collect();
+    if crib.is_empty() {
+        return Vec::new();
+    }
+    all_keys()
+        .filter_map(|key| {
+            let decrypted = Grille::new(key).ok()?.decrypt_blocks(ciphertext);
+            let offset = decrypted.find(&crib)?;
+            Some((key, decrypted[..offset].chars().count()))
+        })
+        .take(MAX_MATCHES)
+        .collect()
+}
+
+pub struct VigenerInferPanel {
+    crib: String,
+    max_period: usize,
+    matches: Option<Vec<VigenerMatch>>,
+}
+
+impl Default for VigenerInferPanel {
+    fn default() -> Self {
+        VigenerInferPanel {
+            crib: String::new(),
+            max_period: 20,
+            matches: None,
+        }
+    }
+}
+
+impl VigenerInferPanel {
+    /* Возвращает true, если ключ был заменён */
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        plaintext: &str,
+        ciphertext: &str,
+        key: &mut String,
+        alphabet: &Alphabet,
+        notifications: &mut Notifications,
+    ) -> bool {
+        let mut applied = false;
+
+        if ui
+            .button(tr("Вывести ключ из пары текстов"))
+            .on_hover_text(tr(
+                "Открытый и зашифрованный тексты должны соответствовать друг другу с начала",
+            ))
+            .clicked()
+        {
+            let keys = vigener_keys(plaintext, ciphertext, alphabet);
+            match keys.first() {
+                Some(found) => {
+                    *key = found.clone();
+                    applied = true;
+                    notifications.info(trf(
+                        "Согласованных ключей: {}, применён самый короткий",
+                        &[&keys.len()],
+                    ));
+                }
+                None => {
+                    notifications.warning(tr("Ни один ключ не согласуется с этой парой текстов"))
+                }
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label(tr("Известный фрагмент:"));
+            ui.text_edit_singleline(&mut self.crib);
+        });
+        ui.horizontal(|ui| {
+            ui.label(tr("Длина ключа до"));
+            ui.add(egui::DragValue::new(&mut self.max_period).clamp_range(1..=200));
+            if ui.button(tr("Найти фрагмент в шифртексте")).clicked() {
+                self.matches = Some(vigener_crib(
+                    &self.crib,
+                    ciphertext,
+                    self.max_period,
+                    alphabet,
+                ));
+            }
+        });
+
+        match &self.matches {
+            Some(matches) if matches.is_empty() => {
+                ui.label(tr(
+                    "Фрагмент ни в одной позиции не даёт согласованного ключа",
+                ));
+            }
+            Some(matches) => {
+                egui::ScrollArea::vertical()
+                    .max_height(150.0)
+                    .show(ui, |ui| {
+                        for found in matches {
+                            ui.horizontal(|ui| {
+                                ui.label(trf("позиция {}:", &[&found.offset]));
+                                ui.monospace(&found.key);
+                                if ui.small_button(tr("Применить")).clicked() {
+                                    *key = found.key.clone();
+                                    applied = true;
+                                }
+                            });
+                        }
+                    });
+            }
+            None => {}
+        }
+        applied
+    }
+}
+
+#[derive(Default)]
+pub struct GrilleInferPanel {
+    crib: String,
+    matches: Option<Vec<(CardboardMatrix, usize)>>,
+}
+
+impl GrilleInferPanel {
+    /* Возвращает true, если ключ был заменён */
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        plaintext: &str,
+        ciphertext: &str,
+        key: &mut CardboardMatrix,
+        notifications: &mut Notifications,
+    ) -> bool {
+        let mut applied = false;
+
+        if ui
+            .button(tr("Вывести ключ из пары текстов"))
+            .on_hover_text(tr(
+                "Открытый и зашифрованный тексты должны соответствовать друг другу с начала",
+            ))
+            .clicked()
+        {
+            let keys = grille_keys(plaintext, ciphertext);
+            match keys.first() {
+                Some(found) => {
+                    *key = *found;
+                    applied = true;
+                    if keys.len() == 1 {
+                        notifications.info(tr("Ключ решётки определён однозначно"));
+                    } else {
+                        notifications.warning(trf(
+                            "Текстам соответствуют {} ключей, применён первый; добавьте текста",
+                            &[&keys.len()],
+                        ));
+                    }
+                }
+                None => {
+                    notifications.warning(tr("Ни один ключ не согласуется с этой парой текстов"))
+                }
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label(tr("Известный фрагмент:"));
+            ui.text_edit_singleline(&mut self.crib);
+            if ui.button(tr("Найти")).clicked() {
+                self.matches = Some(grille_crib(&self.crib, ciphertext));
+            }
+        });
+
+        match &self.matches {
+            Some(matches) if matches.is_empty() => {
+                ui.label(tr("Фрагмент не встречается ни при одном ключе"));
+            }
+            Some(matches) => {
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for (found, offset) in matches {
+                            ui.horizontal(|ui| {
+                                ui.monospace(key_preview(found));
+                                ui.vertical(|ui| {
+                                    ui.label(trf("позиция {}", &[&offset]));
+                                    if ui.small_button(tr("Применить")).clicked() {
+                                        *key = *found;
+                                        applied = true;
+                                    }
+                                });
+                            });
+                        }
+                    });
+            }
+            None => {}
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUSSIAN: &str = "ВЧЕРАВЕЧЕРОММЫДОЛГОГУЛЯЛИПОБЕРЕГУРЕКИИРАЗГОВАРИВАЛИОПОГОДЕ";
+
+    #[test]
+    fn vigener_key_from_full_pair() {
+        let ciphertext = VigenerProgressive::new("КЛЮЧ").unwrap().encrypt(RUSSIAN);
+        let keys = vigener_keys(RUSSIAN, &ciphertext, &Alphabet::default());
+        assert_eq!(keys.first().map(String::as_str), Some("КЛЮЧ"));
+    }
+
+    #[test]
+    fn vigener_key_with_mixed_alphabet() {
+        let alphabet = Alphabet::from_keyword("ЗАМОК");
+        let ciphertext = VigenerProgressive::new("КЛЮЧ")
+            .unwrap()
+            .with_alphabet(alphabet)
+            .encrypt(RUSSIAN);
+        let keys = vigener_keys(RUSSIAN, &ciphertext, &alphabet);
+        assert_eq!(keys.first().map(String::as_str), Some("КЛЮЧ"));
+    }
+
+    #[test]
+    fn vigener_key_from_crib_at_unknown_offset() {
+        let ciphertext = VigenerProgressive::new("ШИФР").unwrap().encrypt(RUSSIAN);
+        let matches = vigener_crib("БЕРЕГУРЕКИ", &ciphertext, 6, &Alphabet::default());
+        assert!(matches.contains(&VigenerMatch {
+            offset: 27,
+            key: "ШИФР".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn grille_key_from_pair_and_crib() {
+        let key = [
+            [false, false, false, true],
+            [false, false, true, false],
+            [true, false, false, false],
+            [false, true, false, false],
+        ];
+        let plaintext = "WEATTACKATDAWNTHENRETREATTOTHEHILLSNORTH";
+        let ciphertext = Grille::new(key).unwrap().encrypt_text(plaintext);
+
+        assert_eq!(grille_keys(plaintext, &ciphertext), vec![key]);
+        assert!(grille_crib("RETREAT", &ciphertext).contains(&(key, 18)));
+    }
+}
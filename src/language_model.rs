@@ -0,0 +1,607 @@
+/* Языковая модель: логарифмы вероятностей n-грамм для оценки правдоподобия текста */
+
+use crate::i18n::{tr, trf};
+use crate::notifications::Notifications;
+use crate::text_encoding::decode_auto;
+use crate::vigener_progressive::{filter_russian, ALPHABET};
+use eframe::egui;
+use im_native_dialog::ImNativeFileDialog;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+pub const LATIN: [char; 26] = [
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S',
+    'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
+];
+
+/* Строятся таблицы от униграмм до квадграмм */
+pub const MAX_ORDER: usize = 4;
+/* Больше букв не бывает ни в одном встроенном алфавите; ограничение не даёт чужому файлу
+ * заставить программу выделить гигабайты под таблицу квадграмм */
+const MAX_ALPHABET: usize = 33;
+/* На маленьком корпусе триграммы надёжнее квадграмм */
+const DEFAULT_ORDER: usize = 3;
+
+const MAGIC: &[u8; 4] = b"TILM";
+const VERSION: u8 = 2;
+/* Логарифмы в файле хранятся в байте с шагом 1/32: хватает до 10^-8 */
+const QUANTUM: f32 = 32.0;
+/* Триграммы и квадграммы, встреченные в корпусе один раз, — скорее шум, чем статистика;
+ * они получают ту же оценку, что и невстреченные, и не занимают места в файле */
+const MIN_COUNT_FROM_TRIGRAMS: u32 = 2;
+
+struct NgramTable {
+    log_probs: Vec<f32>,
+    floor: f32,
+}
+
+pub struct LanguageModel {
+    alphabet: Vec<char>,
+    /* tables[n - 1] — таблица n-грамм */
+    tables: Vec<NgramTable>,
+    order: usize,
+}
+
+impl LanguageModel {
+    /* Считает n-граммы по буквам корпуса; прочие символы разрывают слова, но не n-граммы */
+    pub fn from_corpus(corpus: &str, alphabet: &[char]) -> Self {
+        let mut model = LanguageModel {
+            alphabet: alphabet.to_vec(),
+            tables: Vec::new(),
+            order: DEFAULT_ORDER,
+        };
+        let letters: Vec<usize> = model.letters(corpus).collect();
+
+        for n in 1..=MAX_ORDER {
+            let mut counts = vec![0u32; alphabet.len().pow(n as u32)];
+            for window in letters.windows(n) {
+                counts[model.index(window)] += 1;
+            }
+
+            let total = counts.iter().map(|&c| c as f64).sum::<f64>().max(1.0);
+            let floor = (0.01 / total).log10() as f32;
+            let min_count = if n >= 3 { MIN_COUNT_FROM_TRIGRAMS } else { 1 };
+            let log_probs = counts
+                .into_iter()
+                .map(|count| {
+                    if count >= min_count {
+                        (count as f64 / total).log10() as f32
+                    } else {
+                        floor
+                    }
+                })
+                .collect();
+            model.tables.push(NgramTable { log_probs, floor });
+        }
+        model
+    }
+
+    /* Порядок n-грамм, которым считает score */
+    pub fn with_order(mut self, order: usize) -> Self {
+        self.order = order.clamp(1, MAX_ORDER);
+        self
+    }
+
+    fn letters<'a>(&'a self, text: &'a str) -> impl Iterator<Item = usize> + 'a {
+        text.chars().filter_map(|c| {
+            let c = c.to_uppercase().next()?;
+            self.alphabet.iter().position(|&l| l == c)
+        })
+    }
+
+    fn index(&self, window: &[usize]) -> usize {
+        window
+            .iter()
+            .fold(0, |index, &letter| index * self.alphabet.len() + letter)
+    }
+
+    /* Сумма log10 вероятностей всех n-грамм текста; чем больше, тем текст правдоподобнее */
+    pub fn score(&self, text: &str) -> f64 {
+        self.score_order(text, self.order)
+    }
+
+    /* Порядок вне 1..=MAX_ORDER приводится к ближайшему допустимому */
+    pub fn score_order(&self, text: &str, n: usize) -> f64 {
+        let n = n.clamp(1, MAX_ORDER);
+        let table = &self.tables[n - 1];
+        let letters: Vec<usize> = self.letters(text).collect();
+        letters
+            .windows(n)
+            .map(|window| table.log_probs[self.index(window)] as f64)
+            .sum()
+    }
+
+    /* Оценка на одну n-грамму, чтобы сравнивать тексты разной длины */
+    pub fn score_per_letter(&self, text: &str) -> f64 {
+        let ngrams = self.letters(text).count().saturating_sub(self.order - 1);
+        self.score(text) / ngrams.max(1) as f64
+    }
+
+    /* Формат файла: "TILM", версия, алфавит, порядок по умолчанию и разреженные таблицы.
+     * В таблице хранятся только n-граммы с оценкой выше нижней: расстояние от предыдущего
+     * индекса в LEB128 и -log10 * 32 в байте. */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(self.alphabet.len() as u8);
+        for &c in &self.alphabet {
+            bytes.extend((c as u32).to_le_bytes());
+        }
+        bytes.push(self.order as u8);
+        bytes.push(self.tables.len() as u8);
+
+        for table in &self.tables {
+            bytes.extend(table.floor.to_le_bytes());
+            let seen: Vec<_> = table
+                .log_probs
+                .iter()
+                .enumerate()
+                .filter(|(_, &log_prob)| log_prob != table.floor)
+                .collect();
+            bytes.extend((seen.len() as u32).to_le_bytes());
+            let mut next = 0;
+            for (index, log_prob) in seen {
+                push_varint(&mut bytes, index - next);
+                next = index + 1;
+                bytes.push((-log_prob * QUANTUM).round().clamp(0.0, u8::MAX as f32) as u8);
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(invalid("это не файл языковой модели"));
+        }
+        if reader.u8()? != VERSION {
+            return Err(invalid("неподдерживаемая версия языковой модели"));
+        }
+
+        let alphabet_len = reader.u8()? as usize;
+        let alphabet = (0..alphabet_len)
+            .map(|_| {
+                char::from_u32(reader.u32()?).ok_or_else(|| invalid("неверная буква алфавита"))
+            })
+            .collect::<io::Result<Vec<char>>>()?;
+        if alphabet.is_empty() {
+            return Err(invalid("пустой алфавит"));
+        }
+        if alphabet.len() > MAX_ALPHABET {
+            return Err(invalid("слишком большой алфавит"));
+        }
+        let order = reader.u8()? as usize;
+        let table_count = reader.u8()? as usize;
+        if table_count != MAX_ORDER || !(1..=MAX_ORDER).contains(&order) {
+            return Err(invalid("неверное число таблиц"));
+        }
+
+        let mut tables = Vec::new();
+        for n in 1..=table_count {
+            let floor = reader.f32()?;
+            let size = alphabet
+                .len()
+                .checked_pow(n as u32)
+                .filter(|&size| size <= MAX_ALPHABET.pow(MAX_ORDER as u32))
+                .ok_or_else(|| invalid("слишком большая таблица n-грамм"))?;
+            let mut log_probs = vec![floor; size];
+            let mut next = 0;
+            for _ in 0..reader.u32()? {
+                let index = next + reader.varint()?;
+                *log_probs
+                    .get_mut(index)
+                    .ok_or_else(|| invalid("индекс n-граммы вне таблицы"))? =
+                    -(reader.u8()? as f32) / QUANTUM;
+                next = index + 1;
+            }
+            tables.push(NgramTable { log_probs, floor });
+        }
+
+        Ok(LanguageModel {
+            alphabet,
+            tables,
+            order,
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+}
+
+/* Беззнаковое число по 7 бит в байте, старший бит — «дальше есть ещё байт» */
+fn push_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("файл языковой модели обрезан"))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> io::Result<usize> {
+        let mut value = 0;
+        for shift in (0..32).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("слишком длинное число в файле языковой модели"))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Language {
+    Russian,
+    English,
+}
+
+pub const LANGUAGES: [Language; 2] = [Language::Russian, Language::English];
+
+impl Language {
+    /* Язык текста по тому, каких букв в нём больше; перестановка букв его не меняет,
+     * так что годится и для шифртекста решётки */
+    pub fn of_text(text: &str) -> Language {
+        let russian = filter_russian(text.chars()).count();
+        let latin = text.chars().filter(char::is_ascii_alphabetic).count();
+        if latin > russian {
+            Language::English
+        } else {
+            Language::Russian
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Russian => tr("Русский"),
+            Language::English => tr("Английский"),
+        }
+    }
+
+    pub fn alphabet(self) -> &'static [char] {
+        match self {
+            Language::Russian => &ALPHABET,
+            Language::English => &LATIN,
+        }
+    }
+
+    /* Тексты, из которых собраны встроенные таблицы */
+    pub fn corpus(self) -> &'static str {
+        match self {
+            Language::Russian => include_str!("../corpus/ru.txt"),
+            Language::English => include_str!("../corpus/en.txt"),
+        }
+    }
+
+    /* Встроенные таблицы собраны из текстов в corpus; пересобрать: cargo test -- --ignored */
+    fn embedded(self) -> &'static [u8] {
+        match self {
+            Language::Russian => include_bytes!("../corpus/ru.lm"),
+            Language::English => include_bytes!("../corpus/en.lm"),
+        }
+    }
+}
+
+static MODELS: RwLock<[Option<Arc<LanguageModel>>; 2]> = RwLock::new([None, None]);
+
+/* Текущая модель языка, которой пользуются все атаки: встроенная или заданная пользователем */
+pub fn model(language: Language) -> Arc<LanguageModel> {
+    if let Some(model) = &MODELS.read().unwrap()[language as usize] {
+        return model.clone();
+    }
+    MODELS.write().unwrap()[language as usize]
+        .get_or_insert_with(|| Arc::new(LanguageModel::from_bytes(language.embedded()).unwrap()))
+        .clone()
+}
+
+/* None возвращает встроенную модель */
+pub fn set_model(language: Language, model: Option<LanguageModel>) -> io::Result<()> {
+    if let Some(model) = &model {
+        if model.alphabet != language.alphabet() {
+            return Err(invalid("алфавит модели не совпадает с выбранным языком"));
+        }
+    }
+    MODELS.write().unwrap()[language as usize] = model.map(Arc::new);
+    Ok(())
+}
+
+fn poll_path(
+    dialog: &mut ImNativeFileDialog<Option<PathBuf>>,
+    notifications: &mut Notifications,
+) -> Option<PathBuf> {
+    match dialog.check() {
+        Some(Ok(path)) => path,
+        Some(Err(e)) => {
+            notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e]));
+            None
+        }
+        None => None,
+    }
+}
+
+/* Окно управления языковыми моделями: сборка из своего корпуса, загрузка и сохранение.
+ * Параметры сохраняются между запусками, загруженные модели — нет */
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelsWindow {
+    language: Language,
+    order: usize,
+    #[serde(skip)]
+    corpus_dialog: ImNativeFileDialog<Option<PathBuf>>,
+    #[serde(skip)]
+    load_dialog: ImNativeFileDialog<Option<PathBuf>>,
+    #[serde(skip)]
+    save_dialog: ImNativeFileDialog<Option<PathBuf>>,
+    /* Откуда взята текущая модель каждого языка; None — встроенная */
+    #[serde(skip)]
+    sources: [Option<String>; 2],
+    sample: String,
+}
+
+impl Default for ModelsWindow {
+    fn default() -> Self {
+        ModelsWindow {
+            language: Language::Russian,
+            order: DEFAULT_ORDER,
+            corpus_dialog: Default::default(),
+            load_dialog: Default::default(),
+            save_dialog: Default::default(),
+            sources: Default::default(),
+            sample: String::new(),
+        }
+    }
+}
+
+impl ModelsWindow {
+    fn replace(
+        &mut self,
+        language: Language,
+        model: LanguageModel,
+        source: &Path,
+        notifications: &mut Notifications,
+    ) {
+        match set_model(language, Some(model)) {
+            Ok(()) => {
+                self.sources[language as usize] = Some(source.display().to_string());
+                notifications.info(trf(
+                    "Модель языка «{}» заменена: {}",
+                    &[&language.name(), &source.display()],
+                ));
+            }
+            Err(e) => notifications.error(format!("{}: {e}", source.display())),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, notifications: &mut Notifications) {
+        ui.horizontal(|ui| {
+            for option in LANGUAGES {
+                ui.selectable_value(&mut self.language, option, option.name());
+            }
+        });
+        let language = self.language;
+
+        if let Some(path) = poll_path(&mut self.corpus_dialog, notifications) {
+            match std::fs::read(&path) {
+                Ok(bytes) => {
+                    let corpus = decode_auto(&bytes).text;
+                    let model = LanguageModel::from_corpus(&corpus, language.alphabet())
+                        .with_order(self.order);
+                    self.replace(language, model, &path, notifications);
+                }
+                Err(e) => {
+                    notifications.error(trf("Не удалось прочитать {}: {}", &[&path.display(), &e]))
+                }
+            }
+        }
+        if let Some(path) = poll_path(&mut self.load_dialog, notifications) {
+            match LanguageModel::load(&path) {
+                Ok(model) => self.replace(language, model, &path, notifications),
+                Err(e) => {
+                    notifications.error(trf("Не удалось загрузить {}: {}", &[&path.display(), &e]))
+                }
+            }
+        }
+        if let Some(path) = poll_path(&mut self.save_dialog, notifications) {
+            match model(language).save(&path) {
+                Ok(()) => notifications.info(trf("Модель сохранена в {}", &[&path.display()])),
+                Err(e) => {
+                    notifications.error(trf("Не удалось записать {}: {}", &[&path.display(), &e]))
+                }
+            }
+        }
+
+        let source = &mut self.sources[language as usize];
+        ui.horizontal(|ui| {
+            match source {
+                Some(path) => ui.label(trf("Текущая модель: {}", &[&path])),
+                None => ui.label(tr("Текущая модель: встроенная")),
+            };
+            if source.is_some() && ui.button(tr("Вернуть встроенную")).clicked() {
+                set_model(language, None).unwrap();
+                *source = None;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label(tr("При сборке оценивать n-граммами длины"));
+            ui.add(egui::DragValue::new(&mut self.order).clamp_range(1..=MAX_ORDER));
+        });
+        ui.horizontal(|ui| {
+            let dialogs = [
+                (tr("Собрать из корпуса..."), &mut self.corpus_dialog, false),
+                (tr("Загрузить модель..."), &mut self.load_dialog, false),
+                (tr("Сохранить модель..."), &mut self.save_dialog, true),
+            ];
+            for (label, dialog, save) in dialogs {
+                if ui.button(label).clicked() {
+                    let result = if save {
+                        dialog.show_save_single_file(None)
+                    } else {
+                        dialog.open_single_file(None)
+                    };
+                    if let Err(e) = result {
+                        notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                    }
+                }
+            }
+        });
+
+        ui.separator();
+        ui.label(tr("Проверить текст:"));
+        ui.text_edit_multiline(&mut self.sample);
+        ui.label(trf(
+            "Оценка на n-грамму: {}",
+            &[&format!(
+                "{:.2}",
+                model(language).score_per_letter(&self.sample)
+            )],
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn built_english() -> LanguageModel {
+        LanguageModel::from_corpus(Language::English.corpus(), &LATIN)
+    }
+
+    fn built_russian() -> LanguageModel {
+        LanguageModel::from_corpus(Language::Russian.corpus(), &ALPHABET)
+    }
+
+    #[test]
+    fn model_must_match_language() {
+        assert!(set_model(Language::Russian, Some(built_english())).is_err());
+    }
+
+    #[test]
+    #[ignore]
+    fn regenerate_embedded_tables() {
+        built_english().save(Path::new("corpus/en.lm")).unwrap();
+        built_russian().save(Path::new("corpus/ru.lm")).unwrap();
+    }
+
+    #[test]
+    fn embedded_tables_match_corpus() {
+        assert!(built_english().to_bytes() == Language::English.embedded());
+        assert!(built_russian().to_bytes() == Language::Russian.embedded());
+        for language in LANGUAGES {
+            assert!(language.embedded().len() < language.corpus().len() / 2);
+        }
+    }
+
+    #[test]
+    fn serialisation_round_trip() {
+        let model = built_english().with_order(4);
+        let loaded = LanguageModel::from_bytes(&model.to_bytes()).unwrap();
+        assert_eq!(loaded.to_bytes(), model.to_bytes());
+        assert!((loaded.score("THEQUICKBROWNFOX") - model.score("THEQUICKBROWNFOX")).abs() < 0.1);
+        assert!(LanguageModel::from_bytes(&model.to_bytes()[..100]).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_alphabet() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(255);
+        for c in ('\u{100}'..).take(255) {
+            bytes.extend((c as u32).to_le_bytes());
+        }
+        bytes.extend([3, MAX_ORDER as u8]);
+        let error = LanguageModel::from_bytes(&bytes).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "слишком большой алфавит");
+    }
+
+    #[test]
+    fn models_prefer_their_language() {
+        /* Униграммы перестановку букв не замечают */
+        let english = model(Language::English);
+        for n in 2..=MAX_ORDER {
+            let plain = english.score_order("THEQUICKBROWNFOXJUMPSOVERTHELAZYDOG", n);
+            let shuffled = english.score_order("QXTHZEJUCKBOWRNFOIMUPSVOETRHLAYZDGO", n);
+            assert!(plain > shuffled, "order {n}");
+        }
+
+        let russian = model(Language::Russian);
+        let plain = russian.score("СЪЕШЬЖЕЕЩЁЭТИХМЯГКИХФРАНЦУЗСКИХБУЛОК");
+        let shuffled = russian.score("ЪСЕЬШЖЕЩЕЁТЭХИЯМКГХИРФНАЦЗУКСИХУБКЛО");
+        assert!(plain > shuffled);
+    }
+
+    #[test]
+    fn out_of_range_orders_are_clamped() {
+        let english = model(Language::English);
+        let text = "THEQUICKBROWNFOX";
+        assert_eq!(english.score_order(text, 0), english.score_order(text, 1));
+        assert_eq!(
+            english.score_order(text, MAX_ORDER + 1),
+            english.score_order(text, MAX_ORDER)
+        );
+        assert_eq!(english.score_order("", 5), 0.0);
+    }
+
+    #[test]
+    fn score_ignores_case_and_punctuation() {
+        let english = model(Language::English);
+        assert_eq!(
+            english.score("the cat, the hat!"),
+            english.score("THECATTHEHAT")
+        );
+    }
+
+    #[test]
+    fn window_keeps_parameters_but_not_sources() {
+        let mut window = ModelsWindow {
+            language: Language::English,
+            order: 3,
+            sample: "пример".to_owned(),
+            ..Default::default()
+        };
+        window.sources[0] = Some("корпус.txt".to_owned());
+        let restored: ModelsWindow = ron::from_str(&ron::to_string(&window).unwrap()).unwrap();
+        assert_eq!(restored.language, Language::English);
+        assert_eq!((restored.order, restored.sample.as_str()), (3, "пример"));
+        assert_eq!(restored.sources, [None, None]);
+    }
+}
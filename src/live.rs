@@ -0,0 +1,384 @@
+/* Шифрование на лету: пересчёт противоположного текста при редактировании */
+
+use crate::error::CipherError;
+use crate::i18n::tr;
+use crate::notifications::Notifications;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::Duration;
+
+/* Тексты длиннее этого пересчитываются в фоне и с задержкой */
+const SYNC_LIMIT: usize = 10_000;
+const DEBOUNCE_SECONDS: f64 = 0.3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Direction {
+    /* Источник — открытый текст */
+    #[default]
+    Encrypt,
+    /* Источник — шифртекст */
+    Decrypt,
+}
+
+/* Ошибка шифра не стирает текст: поле остаётся прежним, а ошибка уходит в уведомления */
+pub type Job = Box<dyn FnOnce() -> Result<String, CipherError> + Send>;
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiveMode {
+    pub enabled: bool,
+    pub direction: Direction,
+    #[serde(skip)]
+    dirty_since: Option<f64>,
+    #[serde(skip)]
+    finished: Option<(Direction, Result<String, CipherError>)>,
+    #[serde(skip)]
+    running: Option<(Direction, Receiver<Result<String, CipherError>>)>,
+}
+
+impl LiveMode {
+    /* Запоминает правку; edited — сторона, которую правил пользователь, None для ключа.
+     * Направление задаёт только переключатель: правка стороны, которая пересчитывается,
+     * не запускает обратный пересчёт */
+    pub fn edited(&mut self, edited: Option<Direction>, now: f64) {
+        if !self.enabled {
+            return;
+        }
+        if edited.is_some_and(|edited| edited != self.direction) {
+            /* Результат фоновой задачи затёр бы текст, который сейчас правят */
+            self.running = None;
+            return;
+        }
+        self.dirty_since = Some(now);
+    }
+
+    /* Настройки без состояния текущего пересчёта, для копии вкладки */
+    pub fn settings(&self) -> LiveMode {
+        LiveMode {
+            enabled: self.enabled,
+            direction: self.direction,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /* Запускает пересчёт, если прошла задержка; make_job строит задачу для текущего направления */
+    pub fn update(
+        &mut self,
+        ctx: &egui::Context,
+        source_len: usize,
+        make_job: impl FnOnce(Direction) -> Option<Job>,
+    ) -> Option<(Direction, Result<String, CipherError>)> {
+        if let Some(wait) = self.schedule(ctx.input(|i| i.time), source_len, make_job) {
+            ctx.request_repaint_after(Duration::from_secs_f64(wait));
+        }
+        let result = self.poll();
+        if self.is_busy() {
+            ctx.request_repaint_after(Duration::from_millis(50));
+        }
+        result
+    }
+
+    /* Возвращает, сколько ещё ждать до пересчёта, если задержка не прошла */
+    fn schedule(
+        &mut self,
+        now: f64,
+        source_len: usize,
+        make_job: impl FnOnce(Direction) -> Option<Job>,
+    ) -> Option<f64> {
+        let since = self.dirty_since?;
+        let wait = if source_len < SYNC_LIMIT {
+            0.0
+        } else {
+            DEBOUNCE_SECONDS - (now - since)
+        };
+
+        if wait > 0.0 {
+            return Some(wait);
+        }
+        self.dirty_since = None;
+        if let Some(job) = make_job(self.direction) {
+            self.start(source_len, job);
+        }
+        None
+    }
+
+    fn start(&mut self, source_len: usize, job: Job) {
+        if source_len < SYNC_LIMIT {
+            self.running = None;
+            self.finished = Some((self.direction, job()));
+        } else {
+            /* Предыдущая задача, если она ещё идёт, будет проигнорирована */
+            let (sender, receiver) = channel();
+            std::thread::spawn(move || {
+                let _ = sender.send(job());
+            });
+            self.running = Some((self.direction, receiver));
+        }
+    }
+
+    fn poll(&mut self) -> Option<(Direction, Result<String, CipherError>)> {
+        if let Some(finished) = self.finished.take() {
+            return Some(finished);
+        }
+
+        let (direction, receiver) = self.running.as_ref()?;
+        match receiver.try_recv() {
+            Ok(result) => {
+                let direction = *direction;
+                self.running = None;
+                Some((direction, result))
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.running = None;
+                None
+            }
+        }
+    }
+}
+
+/* Подставляет результат пересчёта в соответствующее поле вкладки. Ошибка, которая
+ * повторяется, пока пользователь печатает, сообщается один раз */
+pub fn apply_live_result(
+    ctx: &egui::Context,
+    result: Option<(Direction, Result<String, CipherError>)>,
+    input_text: &mut String,
+    output_text: &mut String,
+    notifications: &mut Notifications,
+) {
+    match result {
+        Some((Direction::Encrypt, Ok(text))) => *output_text = text,
+        Some((Direction::Decrypt, Ok(text))) => *input_text = text,
+        Some((_, Err(e))) => {
+            let message = e.to_string();
+            if notifications.last().is_none_or(|last| last.text != message) {
+                notifications.warning(message);
+            }
+            return;
+        }
+        None => return,
+    }
+    ctx.request_repaint();
+}
+
+pub fn live_controls(ui: &mut egui::Ui, live: &mut LiveMode) {
+    let mut changed = ui.checkbox(&mut live.enabled, tr("На лету")).changed();
+    if live.enabled {
+        changed |= ui
+            .selectable_value(&mut live.direction, Direction::Encrypt, tr("из открытого"))
+            .on_hover_text(tr(
+                "Пересчитывать шифртекст при изменении открытого текста и ключа",
+            ))
+            .changed();
+        changed |= ui
+            .selectable_value(&mut live.direction, Direction::Decrypt, tr("из шифртекста"))
+            .on_hover_text(tr(
+                "Пересчитывать открытый текст при изменении шифртекста и ключа",
+            ))
+            .changed();
+        if live.is_busy() {
+            ui.spinner();
+        }
+    }
+    if changed {
+        live.edited(None, ui.input(|i| i.time));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::Level;
+    use std::sync::mpsc::Sender;
+
+    fn live() -> LiveMode {
+        LiveMode {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn job(text: &str) -> impl FnOnce(Direction) -> Option<Job> {
+        let text = text.to_owned();
+        move |_| Some(Box::new(move || Ok(text)) as Job)
+    }
+
+    /* Задача, которая закончится только после отправки в возвращённый канал */
+    fn blocked_job(text: &str) -> (Sender<()>, impl FnOnce(Direction) -> Option<Job>) {
+        let (release, wait) = channel::<()>();
+        let text = text.to_owned();
+        let make_job = move |_| {
+            Some(Box::new(move || {
+                let _ = wait.recv();
+                Ok(text)
+            }) as Job)
+        };
+        (release, make_job)
+    }
+
+    /* Результат без ошибки; ошибка здесь означала бы сбой теста */
+    fn poll(live: &mut LiveMode) -> Option<(Direction, String)> {
+        live.poll()
+            .map(|(direction, text)| (direction, text.unwrap()))
+    }
+
+    fn wait_result(live: &mut LiveMode) -> Option<(Direction, String)> {
+        while live.is_busy() {
+            if let Some(result) = poll(live) {
+                return Some(result);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        poll(live)
+    }
+
+    #[test]
+    fn short_text_is_recomputed_at_once() {
+        let mut live = live();
+        assert_eq!(live.schedule(0.0, 10, job("x")), None);
+        assert_eq!(poll(&mut live), None);
+
+        live.edited(None, 1.0);
+        assert_eq!(live.schedule(1.0, 10, job("шифр")), None);
+        assert!(!live.is_busy());
+        assert_eq!(
+            poll(&mut live),
+            Some((Direction::Encrypt, "шифр".to_owned()))
+        );
+        assert_eq!(poll(&mut live), None);
+    }
+
+    #[test]
+    fn disabled_mode_ignores_edits() {
+        let mut live = LiveMode::default();
+        live.edited(Some(Direction::Encrypt), 1.0);
+        assert_eq!(live.schedule(1.0, 10, job("x")), None);
+        assert_eq!(poll(&mut live), None);
+    }
+
+    #[test]
+    fn long_text_waits_for_debounce() {
+        let mut live = live();
+        live.direction = Direction::Decrypt;
+        live.edited(Some(Direction::Decrypt), 1.0);
+        let wait = live
+            .schedule(1.1, SYNC_LIMIT, |_| panic!("слишком рано"))
+            .unwrap();
+        assert!((wait - (DEBOUNCE_SECONDS - 0.1)).abs() < 1e-9);
+
+        /* Новая правка отодвигает пересчёт */
+        live.edited(None, 1.2);
+        assert!(live
+            .schedule(1.0 + DEBOUNCE_SECONDS, SYNC_LIMIT, |_| panic!())
+            .is_some());
+
+        let mut started = None;
+        let make_job = |direction| {
+            started = Some(direction);
+            job("открытый")(direction)
+        };
+        assert_eq!(
+            live.schedule(1.2 + DEBOUNCE_SECONDS, SYNC_LIMIT, make_job),
+            None
+        );
+        assert_eq!(started, Some(Direction::Decrypt));
+        assert_eq!(
+            wait_result(&mut live),
+            Some((Direction::Decrypt, "открытый".to_owned()))
+        );
+    }
+
+    #[test]
+    fn locked_direction_ignores_edits_of_the_other_side() {
+        let mut live = live();
+        live.direction = Direction::Decrypt;
+        live.edited(Some(Direction::Encrypt), 0.0);
+        assert_eq!(live.direction, Direction::Decrypt);
+        assert_eq!(live.schedule(0.0, 1, |_| panic!("пересчёт не нужен")), None);
+
+        /* Правка ключа и шифртекста пересчитывает открытый текст */
+        live.edited(None, 1.0);
+        live.schedule(1.0, 1, job("x"));
+        assert_eq!(poll(&mut live).unwrap().0, Direction::Decrypt);
+        live.edited(Some(Direction::Decrypt), 2.0);
+        live.schedule(2.0, 1, job("x"));
+        assert_eq!(poll(&mut live).unwrap().0, Direction::Decrypt);
+    }
+
+    #[test]
+    fn task_is_dropped_when_its_output_is_edited() {
+        let mut live = live();
+        live.edited(Some(Direction::Encrypt), 0.0);
+        let (release, make_job) = blocked_job("шифртекст");
+        live.schedule(1.0, SYNC_LIMIT, make_job);
+        assert!(live.is_busy());
+        assert_eq!(poll(&mut live), None);
+
+        /* Пока задача шла, пользователь начал править шифртекст */
+        live.edited(Some(Direction::Decrypt), 1.5);
+        assert!(!live.is_busy());
+        release.send(()).unwrap();
+        assert_eq!(wait_result(&mut live), None);
+    }
+
+    #[test]
+    fn same_direction_task_survives_further_edits() {
+        let mut live = live();
+        live.edited(Some(Direction::Encrypt), 0.0);
+        let (release, make_job) = blocked_job("старый");
+        live.schedule(1.0, SYNC_LIMIT, make_job);
+        live.edited(Some(Direction::Encrypt), 1.5);
+        assert!(live.is_busy());
+        release.send(()).unwrap();
+        assert_eq!(
+            wait_result(&mut live),
+            Some((Direction::Encrypt, "старый".to_owned()))
+        );
+    }
+
+    #[test]
+    fn newer_task_replaces_running_one() {
+        let mut live = live();
+        live.edited(Some(Direction::Encrypt), 0.0);
+        let (release, make_job) = blocked_job("старый");
+        live.schedule(1.0, SYNC_LIMIT, make_job);
+        live.edited(None, 1.5);
+        live.schedule(2.0, SYNC_LIMIT, job("новый"));
+        release.send(()).unwrap();
+        assert_eq!(
+            wait_result(&mut live),
+            Some((Direction::Encrypt, "новый".to_owned()))
+        );
+    }
+
+    #[test]
+    fn errors_keep_the_text() {
+        let ctx = egui::Context::default();
+        let mut notifications = Notifications::default();
+        let (mut input, mut output) = ("открытый".to_owned(), "ШИФР".to_owned());
+        let mut live = live();
+        live.direction = Direction::Decrypt;
+        live.edited(Some(Direction::Decrypt), 0.0);
+        live.schedule(0.0, 10, |_| {
+            Some(Box::new(|| Err(CipherError::MalformedCiphertext { letters: 4 })) as Job)
+        });
+        let result = live.poll();
+        apply_live_result(&ctx, result, &mut input, &mut output, &mut notifications);
+        assert_eq!(input, "открытый");
+        let warning = notifications.last().unwrap();
+        assert_eq!(warning.level, Level::Warning);
+        assert_eq!(
+            warning.text,
+            CipherError::MalformedCiphertext { letters: 4 }.to_string()
+        );
+
+        let result = Some((Direction::Encrypt, Ok("НОВЫЙ".to_owned())));
+        apply_live_result(&ctx, result, &mut input, &mut output, &mut notifications);
+        assert_eq!(output, "НОВЫЙ");
+    }
+}
@@ -1,43 +1,479 @@
 mod rotating_grille;
-use egui::vec2;
 use rotating_grille::*;
 
+mod grille_key;
+use grille_key::*;
+
 mod vigener_progressive;
 use vigener_progressive::*;
 
-use egui_dock::Tree;
-use itertools::Itertools;
+mod notifications;
+use notifications::*;
+
+mod text_encoding;
+use text_encoding::*;
+
+mod live;
+use live::*;
+
+mod settings;
+use settings::*;
+
+mod language_model;
+use language_model::ModelsWindow;
+mod task;
+
+mod grille_attack;
+use grille_attack::*;
+mod known_plaintext;
+use known_plaintext::*;
+mod vigener_attack;
+use vigener_attack::DictionaryPanel;
+
+mod pipeline;
+use pipeline::*;
+
+mod key_file;
+use key_file::KeyFileButtons;
+mod passphrase;
+use passphrase::PassphrasePanel;
+
+mod container;
+use container::*;
+mod diff;
+use diff::ComparePanel;
+mod avalanche;
+use avalanche::AvalanchePanel;
+mod report;
+use report::{AttackMethod, AttackResult, Report, ReportButton};
+mod card;
+use card::CardPanel;
+mod exercise;
+use exercise::ExercisesWindow;
+
+mod cli;
+mod error;
+mod i18n;
+use i18n::{tr, trf};
+
+use egui_dock::{Node, NodeIndex, Tree};
+use serde::{Deserialize, Serialize};
 
 use im_native_dialog::ImNativeFileDialog;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+/* Панели и кнопки вкладки, которые живут только до закрытия программы. Вкладка
+ * пользуется теми, что подходят её шифру, остальные так и остаются пустыми */
+#[derive(Default)]
+struct TabTools {
+    container: ContainerSlot,
+    compare: ComparePanel,
+    report: ReportButton,
+    key_files: KeyFileButtons,
+    phrase: PassphrasePanel,
+    vigener_infer: VigenerInferPanel,
+    dictionary: DictionaryPanel,
+    grille_infer: GrilleInferPanel,
+    attack: AttackPanel,
+    card: CardPanel,
+}
+
+#[derive(Serialize, Deserialize)]
 enum EncryptTab {
     Vigener {
+        #[serde(skip)]
         input_file_read_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
         input_file_write_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
         output_file_read_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
         output_file_write_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
 
         input_text: String,
         output_text: String,
         key: String,
+        /* Второй ключ: ключевое слово или перестановка алфавита таблицы */
+        #[serde(default)]
+        alphabet_key: String,
+        encoding: Option<TextEncoding>,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        live: LiveMode,
+        #[serde(skip)]
+        tools: TabTools,
     },
     Grille {
+        #[serde(skip)]
         input_file_read_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
         input_file_write_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
         output_file_read_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
         output_file_write_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
 
         input_text: String,
         output_text: String,
         key: CardboardMatrix,
+        encoding: Option<TextEncoding>,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        live: LiveMode,
+        #[serde(skip)]
+        tools: TabTools,
+    },
+    Pipeline {
+        #[serde(skip)]
+        input_file_read_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
+        input_file_write_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
+        output_file_read_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
+        output_file_write_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
+        definition_read_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+        #[serde(skip)]
+        definition_write_path_dialog: ImNativeFileDialog<Option<PathBuf>>,
+
+        input_text: String,
+        output_text: String,
+        pipeline: Pipeline,
+        encoding: Option<TextEncoding>,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        live: LiveMode,
+        #[serde(skip)]
+        tools: TabTools,
+    },
+    Avalanche {
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        panel: AvalanchePanel,
     },
 }
 
-struct TabViewer {}
-impl egui_dock::TabViewer for TabViewer {
+impl EncryptTab {
+    fn new_vigener() -> Self {
+        EncryptTab::Vigener {
+            input_file_read_path_dialog: Default::default(),
+            input_file_write_path_dialog: Default::default(),
+            output_file_read_path_dialog: Default::default(),
+            output_file_write_path_dialog: Default::default(),
+
+            input_text: String::new(),
+            output_text: String::new(),
+            key: String::new(),
+            alphabet_key: String::new(),
+            encoding: None,
+            name: String::new(),
+            live: LiveMode::default(),
+            tools: TabTools::default(),
+        }
+    }
+
+    fn new_grille() -> Self {
+        EncryptTab::Grille {
+            input_file_read_path_dialog: Default::default(),
+            input_file_write_path_dialog: Default::default(),
+            output_file_read_path_dialog: Default::default(),
+            output_file_write_path_dialog: Default::default(),
+
+            input_text: String::new(),
+            output_text: String::new(),
+            key: [[false; 4]; 4],
+            encoding: None,
+            name: String::new(),
+            live: LiveMode::default(),
+            tools: TabTools::default(),
+        }
+    }
+
+    fn new_pipeline() -> Self {
+        EncryptTab::Pipeline {
+            input_file_read_path_dialog: Default::default(),
+            input_file_write_path_dialog: Default::default(),
+            output_file_read_path_dialog: Default::default(),
+            output_file_write_path_dialog: Default::default(),
+            definition_read_path_dialog: Default::default(),
+            definition_write_path_dialog: Default::default(),
+
+            input_text: String::new(),
+            output_text: String::new(),
+            pipeline: Pipeline::default(),
+            encoding: None,
+            name: String::new(),
+            live: LiveMode::default(),
+            tools: TabTools::default(),
+        }
+    }
+
+    fn new_avalanche() -> Self {
+        EncryptTab::Avalanche {
+            name: String::new(),
+            panel: AvalanchePanel::default(),
+        }
+    }
+
+    /* Вкладка для шифра из контейнера; ключ пользователь вводит сам */
+    fn from_container(container: Container, title: String) -> Self {
+        let mut tab = match container.cipher {
+            CipherKind::Vigener => Self::new_vigener(),
+            CipherKind::Grille => Self::new_grille(),
+            CipherKind::Pipeline => Self::new_pipeline(),
+        };
+        if let EncryptTab::Pipeline { pipeline, .. } = &mut tab {
+            pipeline.stages = container.stages.iter().map(|stage| stage.blank()).collect();
+        }
+        match &mut tab {
+            EncryptTab::Vigener {
+                output_text,
+                name,
+                tools,
+                ..
+            }
+            | EncryptTab::Grille {
+                output_text,
+                name,
+                tools,
+                ..
+            }
+            | EncryptTab::Pipeline {
+                output_text,
+                name,
+                tools,
+                ..
+            } => {
+                *output_text = container.ciphertext.clone();
+                *name = title;
+                tools.container.loaded = Some(container);
+            }
+            EncryptTab::Avalanche { .. } => {}
+        }
+        tab
+    }
+
+    /* Копия вкладки с тем же ключом и текстами, но без открытых диалогов */
+    fn duplicate(&self) -> Self {
+        match self {
+            EncryptTab::Vigener {
+                input_text,
+                output_text,
+                key,
+                alphabet_key,
+                encoding,
+                live,
+                ..
+            } => EncryptTab::Vigener {
+                input_file_read_path_dialog: Default::default(),
+                input_file_write_path_dialog: Default::default(),
+                output_file_read_path_dialog: Default::default(),
+                output_file_write_path_dialog: Default::default(),
+
+                input_text: input_text.clone(),
+                output_text: output_text.clone(),
+                key: key.clone(),
+                alphabet_key: alphabet_key.clone(),
+                encoding: *encoding,
+                name: trf("{} (копия)", &[&self.title()]),
+                live: live.settings(),
+                tools: TabTools::default(),
+            },
+            EncryptTab::Grille {
+                input_text,
+                output_text,
+                key,
+                encoding,
+                live,
+                ..
+            } => EncryptTab::Grille {
+                input_file_read_path_dialog: Default::default(),
+                input_file_write_path_dialog: Default::default(),
+                output_file_read_path_dialog: Default::default(),
+                output_file_write_path_dialog: Default::default(),
+
+                input_text: input_text.clone(),
+                output_text: output_text.clone(),
+                key: *key,
+                encoding: *encoding,
+                name: trf("{} (копия)", &[&self.title()]),
+                live: live.settings(),
+                tools: TabTools::default(),
+            },
+            EncryptTab::Pipeline {
+                input_text,
+                output_text,
+                pipeline,
+                encoding,
+                live,
+                ..
+            } => EncryptTab::Pipeline {
+                input_file_read_path_dialog: Default::default(),
+                input_file_write_path_dialog: Default::default(),
+                output_file_read_path_dialog: Default::default(),
+                output_file_write_path_dialog: Default::default(),
+                definition_read_path_dialog: Default::default(),
+                definition_write_path_dialog: Default::default(),
+
+                input_text: input_text.clone(),
+                output_text: output_text.clone(),
+                pipeline: pipeline.clone(),
+                encoding: *encoding,
+                name: trf("{} (копия)", &[&self.title()]),
+                live: live.settings(),
+                tools: TabTools::default(),
+            },
+            EncryptTab::Avalanche { panel, .. } => EncryptTab::Avalanche {
+                name: trf("{} (копия)", &[&self.title()]),
+                panel: panel.duplicate(),
+            },
+        }
+    }
+
+    fn kind_title(&self) -> &'static str {
+        match self {
+            EncryptTab::Vigener { .. } => tr("Виженер"),
+            EncryptTab::Grille { .. } => tr("Вращающаяся решётка"),
+            EncryptTab::Pipeline { .. } => tr("Каскад шифров"),
+            EncryptTab::Avalanche { .. } => tr("Лавинный эффект"),
+        }
+    }
+
+    fn name_mut(&mut self) -> &mut String {
+        match self {
+            EncryptTab::Vigener { name, .. }
+            | EncryptTab::Grille { name, .. }
+            | EncryptTab::Pipeline { name, .. }
+            | EncryptTab::Avalanche { name, .. } => name,
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            EncryptTab::Vigener { name, .. }
+            | EncryptTab::Grille { name, .. }
+            | EncryptTab::Pipeline { name, .. }
+            | EncryptTab::Avalanche { name, .. }
+                if !name.trim().is_empty() =>
+            {
+                name.clone()
+            }
+            _ => self.kind_title().to_owned(),
+        }
+    }
+}
+
+/* Возвращает true, если текст был заменён содержимым файла.
+ * Кодировка None означает автоматическое определение при загрузке и UTF-8 при сохранении */
+fn load_text_file(
+    path: &Path,
+    text: &mut String,
+    encoding: Option<TextEncoding>,
+    notifications: &mut Notifications,
+) -> bool {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            notifications.error(trf("Не удалось прочитать {}: {}", &[&path.display(), &e]));
+            return false;
+        }
+    };
+
+    let decoded = match encoding {
+        Some(encoding) => encoding.decode(&bytes),
+        None => decode_auto(&bytes),
+    };
+    *text = decoded.text;
+
+    let message = trf(
+        "Загружен файл {} ({})",
+        &[&path.display(), &decoded.encoding.name()],
+    );
+    if decoded.had_errors {
+        notifications.warning(trf("{}: недопустимые байты заменены на «�»", &[&message]));
+    } else {
+        notifications.info(message);
+    }
+    true
+}
+
+fn save_text_file(
+    path: &Path,
+    text: &str,
+    encoding: Option<TextEncoding>,
+    notifications: &mut Notifications,
+) {
+    let encoding = encoding.unwrap_or(TextEncoding::Utf8);
+    let (bytes, had_errors) = encoding.encode(text);
+    match std::fs::write(path, bytes) {
+        Ok(()) if had_errors => notifications.warning(trf(
+            "Сохранён файл {} ({}): часть символов не представима в этой кодировке",
+            &[&path.display(), &encoding.name()],
+        )),
+        Ok(()) => notifications.info(trf(
+            "Сохранён файл {} ({})",
+            &[&path.display(), &encoding.name()],
+        )),
+        Err(e) => notifications.error(trf("Не удалось записать {}: {}", &[&path.display(), &e])),
+    }
+}
+
+fn poll_load_dialog(
+    dialog: &mut ImNativeFileDialog<Option<PathBuf>>,
+    text: &mut String,
+    encoding: Option<TextEncoding>,
+    notifications: &mut Notifications,
+) -> bool {
+    match dialog.check() {
+        Some(Ok(Some(path))) => load_text_file(&path, text, encoding, notifications),
+        Some(Err(e)) => {
+            notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e]));
+            false
+        }
+        _ => false,
+    }
+}
+
+fn poll_save_dialog(
+    dialog: &mut ImNativeFileDialog<Option<PathBuf>>,
+    text: &str,
+    encoding: Option<TextEncoding>,
+    notifications: &mut Notifications,
+) {
+    match dialog.check() {
+        Some(Ok(Some(path))) => save_text_file(&path, text, encoding, notifications),
+        Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+        _ => {}
+    }
+}
+
+fn encoding_combo(ui: &mut egui::Ui, id_source: &str, encoding: &mut Option<TextEncoding>) {
+    ui.label(tr("Кодировка файлов:"));
+    egui::ComboBox::from_id_source(id_source)
+        .selected_text(encoding.map_or(tr("Авто"), TextEncoding::name))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(encoding, None, tr("Авто"));
+            for option in ENCODINGS {
+                ui.selectable_value(encoding, Some(option), option.name());
+            }
+        });
+}
+
+struct TabViewer<'a> {
+    notifications: &'a mut Notifications,
+    added_tabs: Vec<(NodeIndex, EncryptTab)>,
+    duplicated_tabs: Vec<EncryptTab>,
+}
+
+impl egui_dock::TabViewer for TabViewer<'_> {
     type Tab = EncryptTab;
     fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Self::Tab) {
+        let title = tab.title();
         match tab {
             EncryptTab::Vigener {
                 input_file_read_path_dialog,
@@ -48,91 +484,313 @@ impl egui_dock::TabViewer for TabViewer {
                 input_text,
                 output_text,
                 key,
+                alphabet_key,
+                encoding,
+                live,
+                name,
+                tools,
             } => {
+                let TabTools {
+                    vigener_infer: infer,
+                    dictionary,
+                    key_files,
+                    phrase,
+                    container,
+                    compare,
+                    report,
+                    ..
+                } = tools;
+                let now = ui.input(|i| i.time);
+
                 ui.horizontal(|ui| {
-                    ui.label("Ключ: ");
-                    ui.text_edit_singleline(key);
+                    ui.label(tr("Ключ: "));
+                    if ui.text_edit_singleline(key).changed() {
+                        live.edited(None, now);
+                    }
+                    /* Пустое поле — ещё не ошибка, кнопки просто неактивны */
+                    if !key.trim().is_empty() {
+                        if let Err(e) = VigenerProgressive::new(key) {
+                            ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                        }
+                    }
+                    ui.separator();
+                    let current = Stage::Vigener {
+                        key: key.clone(),
+                        alphabet_key: alphabet_key.clone(),
+                    };
+                    if let Some(Stage::Vigener {
+                        key: loaded,
+                        alphabet_key: loaded_alphabet,
+                    }) = key_files.ui(ui, current, name, self.notifications)
+                    {
+                        *key = loaded;
+                        *alphabet_key = loaded_alphabet;
+                        live.edited(None, now);
+                    }
+                    ui.separator();
+                    encoding_combo(ui, "vigener_encoding", encoding);
+                    ui.separator();
+                    live_controls(ui, live);
+                    ui.separator();
+                    report.ui(ui, self.notifications, || Report {
+                        title,
+                        stages: vec![Stage::Vigener {
+                            key: key.clone(),
+                            alphabet_key: alphabet_key.clone(),
+                        }],
+                        plaintext: input_text.clone(),
+                        ciphertext: output_text.clone(),
+                        attacks: dictionary
+                            .hits()
+                            .iter()
+                            .map(|hit| AttackResult {
+                                method: AttackMethod::Dictionary,
+                                key: hit.key.clone(),
+                                score: hit.score,
+                                plaintext: hit.plaintext.clone(),
+                            })
+                            .collect(),
+                    });
                 });
-
-                if let Some(Ok(Some(path))) = input_file_read_path_dialog.check() {
-                    if let Ok(str) = std::fs::read_to_string(path) {
-                        *input_text = str;
+                ui.horizontal(|ui| {
+                    ui.label(tr("Алфавит таблицы: "));
+                    if ui
+                        .text_edit_singleline(alphabet_key)
+                        .on_hover_text(tr(
+                            "Необязательно: ключевое слово или перестановка всех 33 букв. \
+                             Пусто — обычный алфавит",
+                        ))
+                        .changed()
+                    {
+                        live.edited(None, now);
+                    }
+                    match Alphabet::parse(alphabet_key) {
+                        Ok(alphabet) if filter_russian(alphabet_key.chars()).count() > 0 => {
+                            ui.monospace(alphabet.letters());
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                        }
+                    }
+                });
+                if let Some(passphrase) = phrase.ui(ui) {
+                    if let Some(derived) = passphrase::vigener_key(passphrase) {
+                        *key = derived;
+                        live.edited(None, now);
                     }
                 }
+                let alphabet = Alphabet::parse(alphabet_key);
 
-                if let Some(Ok(Some(path))) = input_file_write_path_dialog.check() {
-                    std::fs::write(path, &input_text).unwrap();
-                }
-
-                if let Some(Ok(Some(path))) = output_file_read_path_dialog.check() {
-                    if let Ok(str) = std::fs::read_to_string(path) {
-                        *output_text = str;
-                    }
+                if poll_load_dialog(
+                    input_file_read_path_dialog,
+                    input_text,
+                    *encoding,
+                    self.notifications,
+                ) {
+                    live.edited(Some(Direction::Encrypt), now);
                 }
+                poll_save_dialog(
+                    input_file_write_path_dialog,
+                    input_text,
+                    *encoding,
+                    self.notifications,
+                );
 
-                if let Some(Ok(Some(path))) = output_file_write_path_dialog.check() {
-                    std::fs::write(path, &output_text).unwrap();
+                if poll_load_dialog(
+                    output_file_read_path_dialog,
+                    output_text,
+                    *encoding,
+                    self.notifications,
+                ) {
+                    live.edited(Some(Direction::Decrypt), now);
                 }
+                poll_save_dialog(
+                    output_file_write_path_dialog,
+                    output_text,
+                    *encoding,
+                    self.notifications,
+                );
 
-                let right_key = filter_russian(key.chars()).count() > 0;
+                let right_key = Stage::vigener(key, alphabet_key).is_ok();
 
                 ui.columns(2, |column| {
                     column[0].group(|ui| {
-                        ui.label("Открытый текст");
-                        ui.text_edit_multiline(input_text);
+                        ui.label(tr("Открытый текст"));
+                        if ui.text_edit_multiline(input_text).changed() {
+                            live.edited(Some(Direction::Encrypt), now);
+                        }
                         if ui
-                            .add_enabled(right_key, egui::Button::new("Получить (расшифровать)"))
+                            .add_enabled(
+                                right_key,
+                                egui::Button::new(tr("Получить (расшифровать)")),
+                            )
                             .clicked()
                         {
-                            let vig = VigenerProgressive::new(key);
-                            if let Some(vig) = vig {
-                                *input_text = vig.decrypt(output_text);
+                            match Stage::vigener(key, alphabet_key) {
+                                Ok(vig) => {
+                                    *input_text =
+                                        container.finish(output_text, vig.decrypt(output_text));
+                                    let stages = [Stage::Vigener {
+                                        key: key.clone(),
+                                        alphabet_key: alphabet_key.clone(),
+                                    }];
+                                    container.check(
+                                        output_text,
+                                        input_text,
+                                        &stages,
+                                        self.notifications,
+                                    );
+                                }
+                                Err(e) => self.notifications.warning(e.to_string()),
                             }
                         }
 
                         ui.horizontal(|ui| {
-                            if ui.button("Загрузить...").clicked() {
-                                input_file_read_path_dialog
-                                    .open_single_file(None)
-                                    .expect("Unable to open file_path dialog");
+                            if ui.button(tr("Загрузить...")).clicked() {
+                                if let Err(e) = input_file_read_path_dialog.open_single_file(None) {
+                                    self.notifications
+                                        .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                                }
                             }
 
-                            if ui.button("Сохранить...").clicked() {
-                                input_file_write_path_dialog
-                                    .show_save_single_file(None)
-                                    .expect("Unable to open file_path dialog");
+                            if ui.button(tr("Сохранить...")).clicked() {
+                                if let Err(e) =
+                                    input_file_write_path_dialog.show_save_single_file(None)
+                                {
+                                    self.notifications
+                                        .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                                }
                             }
                         });
                     });
 
                     column[1].group(|ui| {
-                        ui.label("Зашифрованный текст");
-                        ui.text_edit_multiline(output_text);
+                        ui.label(tr("Зашифрованный текст"));
+                        if ui.text_edit_multiline(output_text).changed() {
+                            live.edited(Some(Direction::Decrypt), now);
+                        }
                         if ui
-                            .add_enabled(right_key, egui::Button::new("Получить (зашифровать)"))
+                            .add_enabled(right_key, egui::Button::new(tr("Получить (зашифровать)")))
                             .clicked()
                         {
-                            let vig = VigenerProgressive::new(key);
-                            if let Some(vig) = vig {
-                                *output_text = vig.encrypt(input_text);
+                            match Stage::vigener(key, alphabet_key) {
+                                Ok(vig) => {
+                                    *output_text = vig.encrypt(input_text);
+                                }
+                                Err(e) => self.notifications.warning(e.to_string()),
                             }
                         }
 
                         ui.horizontal(|ui| {
-                            if ui.button("Загрузить...").clicked() {
-                                output_file_read_path_dialog
-                                    .open_single_file(None)
-                                    .expect("Unable to open file_path dialog");
+                            if ui.button(tr("Загрузить...")).clicked() {
+                                if let Err(e) = output_file_read_path_dialog.open_single_file(None)
+                                {
+                                    self.notifications
+                                        .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                                }
                             }
 
-                            if ui.button("Сохранить...").clicked() {
-                                output_file_write_path_dialog
-                                    .show_save_single_file(None)
-                                    .expect("Unable to open file_path dialog");
+                            if ui.button(tr("Сохранить...")).clicked() {
+                                if let Err(e) =
+                                    output_file_write_path_dialog.show_save_single_file(None)
+                                {
+                                    self.notifications
+                                        .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                                }
                             }
+                            ui.separator();
+                            container.ui(
+                                ui,
+                                CipherKind::Vigener,
+                                &[Stage::Vigener {
+                                    key: key.clone(),
+                                    alphabet_key: alphabet_key.clone(),
+                                }],
+                                input_text,
+                                output_text,
+                                self.notifications,
+                            );
                         });
                     });
-                })
+                });
+
+                ui.collapsing(
+                    tr("Ключ по известному открытому тексту"),
+                    |ui| match &alphabet {
+                        Ok(alphabet) => {
+                            if infer.ui(
+                                ui,
+                                input_text,
+                                output_text,
+                                key,
+                                alphabet,
+                                self.notifications,
+                            ) {
+                                live.edited(None, now);
+                            }
+                        }
+                        Err(e) => {
+                            ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                        }
+                    },
+                );
+                ui.collapsing(AttackMethod::Dictionary.title(), |ui| match alphabet {
+                    Ok(alphabet) => {
+                        if dictionary.ui(
+                            ui,
+                            output_text,
+                            key,
+                            alphabet,
+                            input_text,
+                            self.notifications,
+                        ) {
+                            self.notifications.info(tr("Применён ключ из словаря"));
+                        }
+                    }
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                    }
+                });
+                ui.collapsing(tr("Сравнение текстов"), |ui| {
+                    let stage = Stage::Vigener {
+                        key: key.clone(),
+                        alphabet_key: alphabet_key.clone(),
+                    };
+                    compare.ui(
+                        ui,
+                        input_text,
+                        |text| stage.decrypt(&stage.encrypt(text)?),
+                        self.notifications,
+                    );
+                });
+
+                let source_len = match live.direction {
+                    Direction::Encrypt => input_text.len(),
+                    Direction::Decrypt => output_text.len(),
+                };
+                let result = live.update(ui.ctx(), source_len, |direction| {
+                    let vig = Stage::vigener(key, alphabet_key).ok()?;
+                    let job: Job = match direction {
+                        Direction::Encrypt => {
+                            let text = input_text.clone();
+                            Box::new(move || Ok(vig.encrypt(&text)))
+                        }
+                        Direction::Decrypt => {
+                            let text = output_text.clone();
+                            Box::new(move || Ok(vig.decrypt(&text)))
+                        }
+                    };
+                    Some(job)
+                });
+                let result = container.finish_live(output_text, result);
+                apply_live_result(
+                    ui.ctx(),
+                    result,
+                    input_text,
+                    output_text,
+                    self.notifications,
+                );
             }
             EncryptTab::Grille {
                 input_file_read_path_dialog,
@@ -143,246 +801,686 @@ impl egui_dock::TabViewer for TabViewer {
                 input_text,
                 output_text,
                 key,
+                encoding,
+                live,
+                name,
+                tools,
             } => {
-                if let Some(Ok(Some(path))) = input_file_read_path_dialog.check() {
-                    if let Ok(str) = std::fs::read_to_string(path) {
-                        *input_text = str;
-                    }
-                }
+                let TabTools {
+                    attack,
+                    card,
+                    grille_infer: infer,
+                    key_files,
+                    phrase,
+                    container,
+                    compare,
+                    report,
+                    ..
+                } = tools;
+                let now = ui.input(|i| i.time);
 
-                if let Some(Ok(Some(path))) = input_file_write_path_dialog.check() {
-                    std::fs::write(path, &input_text).unwrap();
+                if poll_load_dialog(
+                    input_file_read_path_dialog,
+                    input_text,
+                    *encoding,
+                    self.notifications,
+                ) {
+                    live.edited(Some(Direction::Encrypt), now);
                 }
+                poll_save_dialog(
+                    input_file_write_path_dialog,
+                    input_text,
+                    *encoding,
+                    self.notifications,
+                );
 
-                if let Some(Ok(Some(path))) = output_file_read_path_dialog.check() {
-                    if let Ok(str) = std::fs::read_to_string(path) {
-                        *output_text = str;
-                    }
+                if poll_load_dialog(
+                    output_file_read_path_dialog,
+                    output_text,
+                    *encoding,
+                    self.notifications,
+                ) {
+                    live.edited(Some(Direction::Decrypt), now);
                 }
+                poll_save_dialog(
+                    output_file_write_path_dialog,
+                    output_text,
+                    *encoding,
+                    self.notifications,
+                );
 
-                if let Some(Ok(Some(path))) = output_file_write_path_dialog.check() {
-                    std::fs::write(path, &output_text).unwrap();
-                }
-
-                let right_key = key.iter().flatten().filter(|t| **t).count() == 4;
+                let right_key = is_valid_key(key);
 
+                ui.horizontal(|ui| {
+                    let current = Stage::Grille { key: *key };
+                    if let Some(Stage::Grille { key: loaded }) =
+                        key_files.ui(ui, current, name, self.notifications)
+                    {
+                        *key = loaded;
+                        live.edited(None, now);
+                    }
+                    ui.separator();
+                    encoding_combo(ui, "grille_encoding", encoding);
+                    ui.separator();
+                    live_controls(ui, live);
+                    ui.separator();
+                    report.ui(ui, self.notifications, || Report {
+                        title,
+                        stages: vec![Stage::Grille { key: *key }],
+                        plaintext: input_text.clone(),
+                        ciphertext: output_text.clone(),
+                        attacks: attack
+                            .result()
+                            .map(|candidate| AttackResult {
+                                method: AttackMethod::KeySearch,
+                                key: report::grille_key_line(&candidate.key),
+                                score: candidate.score,
+                                plaintext: candidate.plaintext.clone(),
+                            })
+                            .into_iter()
+                            .collect(),
+                    });
+                });
 
                 ui.columns(2, |column| {
                     column[0].group(|ui| {
-                ui.label("Ключ");
-                {
-                    let size = egui::vec2(10.0, 100.0);
-                    let (response, painter) = ui.allocate_painter(size, egui::Sense::click());
-
-                    let mut local_click = None;
-                    let rect = response.rect;
-
-                    if response.clicked() {
-                        ui.input(|istate| {
-                            let click_pos = istate.pointer.interact_pos().unwrap();
-                            local_click = Some(click_pos - rect.min);
-                        });
-                    }
-
-                    let side = rect.height().min(rect.width());
-                    let cell_size = side / 4.0 - 7.0;
-                    let cell_step = cell_size + 5.;
-
-                    let a = rot_90(key.to_owned());
-                    let b = rot_90(a);
-                    let c = rot_90(b);
-                    let d = rot_90(c);
-
-                    if let Some(click_pos) = local_click {
-                        let x = (click_pos.x / cell_step).floor() as usize;
-                        let y = (click_pos.y / cell_step).floor() as usize;
-
-                        if (0..4).contains(&x) && (0..4).contains(&y) {
-                            let is_disabled =
-                                (a[y][x] || b[y][x] || c[y][x] || d[y][x]) && !(key[y][x]);
-                            if !is_disabled {
-                                key[y][x] = !key[y][x];
-                            }
+                        ui.label(tr("Ключ"));
+                        if ui.add(GrilleKeyEditor::new(key)).changed() {
+                            live.edited(None, now);
                         }
-                    }
-
-                    let color = egui::Color32::from_gray(50);
-                    let disabled_color = egui::Color32::from_gray(240);
-                    let stroke = egui::Stroke::new(2.0, color);
-
-                    let rect = rect.translate(vec2(1.0, 1.0));
-
-                    for y in 0..4 {
-                        for x in 0..4 {
-                            let mut rect = rect;
-                            rect.set_width(cell_size);
-                            rect.set_height(cell_size);
-                            let rect = rect
-                                .translate(egui::vec2(cell_step * x as f32, cell_step * y as f32));
-                            painter.rect_stroke(rect, egui::Rounding::default(), stroke);
-
-                            let is_disabled =
-                                (a[y][x] || b[y][x] || c[y][x] || d[y][x]) && !(key[y][x]);
-
-                            if key[y][x] {
-                                painter.rect_filled(rect, egui::Rounding::default(), color);
-                            } else if is_disabled {
-                                painter.rect_filled(
-                                    rect,
-                                    egui::Rounding::default(),
-                                    disabled_color,
-                                );
+                        grille_key_hints(ui, key);
+                        if let Some(passphrase) = phrase.ui(ui) {
+                            if let Some(derived) = passphrase::grille_key(passphrase) {
+                                *key = derived;
+                                live.edited(None, now);
                             }
                         }
-                    }
-                }
+                    });
+                    column[0].collapsing(tr("Печать решётки"), |ui| {
+                        card.ui(ui, key, self.notifications);
                     });
                     column[0].group(|ui| {
-                        ui.label("Открытый текст");
-                        ui.text_edit_multiline(input_text);
+                        ui.label(tr("Открытый текст"));
+                        if ui.text_edit_multiline(input_text).changed() {
+                            live.edited(Some(Direction::Encrypt), now);
+                        }
 
                         if ui
-                            .add_enabled(right_key, egui::Button::new("Получить (расшифровать)"))
+                            .add_enabled(
+                                right_key,
+                                egui::Button::new(tr("Получить (расшифровать)")),
+                            )
                             .clicked()
                         {
-                            let grille = Grille::new(*key);
-                            let mut chars = output_text
-                                .chars()
-                                .filter(|c| c.is_ascii_alphabetic())
-                                .map(|c| c.to_ascii_uppercase());
-
-                            let mut output = String::new();
-                            'outer: loop {
-                                let mut char_matrix = [[' '; 4]; 4];
-                                for row in &mut char_matrix {
-                                    for ch in row {
-                                        if let Some(char) = chars.next() {
-                                            *ch = char;
-                                        } else {
-                                            break 'outer;
-                                        }
-                                    }
+                            match Grille::new(*key).and_then(|g| g.decrypt_text(output_text)) {
+                                Ok(decrypted) => {
+                                    *input_text = container.finish(output_text, decrypted);
+                                    container.check(
+                                        output_text,
+                                        input_text,
+                                        &[Stage::Grille { key: *key }],
+                                        self.notifications,
+                                    );
                                 }
-                                output.push_str(&grille.decrypt(char_matrix));
+                                Err(e) => self.notifications.warning(e.to_string()),
                             }
-                            *input_text = output;
                         }
 
-                        if ui.button("Загрузить...").clicked() {
-                            input_file_read_path_dialog
-                                .open_single_file(None)
-                                .expect("Unable to open file_path dialog");
+                        if ui.button(tr("Загрузить...")).clicked() {
+                            if let Err(e) = input_file_read_path_dialog.open_single_file(None) {
+                                self.notifications
+                                    .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                            }
                         }
 
-                        if ui.button("Сохранить...").clicked() {
-                            input_file_write_path_dialog
-                                .show_save_single_file(None)
-                                .expect("Unable to open file_path dialog");
+                        if ui.button(tr("Сохранить...")).clicked() {
+                            if let Err(e) = input_file_write_path_dialog.show_save_single_file(None)
+                            {
+                                self.notifications
+                                    .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                            }
                         }
                     });
 
                     column[1].group(|ui| {
-                        ui.label("Зашифрованный текст");
-                        ui.text_edit_multiline(output_text);
+                        ui.label(tr("Зашифрованный текст"));
+                        if ui.text_edit_multiline(output_text).changed() {
+                            live.edited(Some(Direction::Decrypt), now);
+                        }
 
                         if ui
-                            .add_enabled(right_key, egui::Button::new("Получить (зашифровать)"))
+                            .add_enabled(right_key, egui::Button::new(tr("Получить (зашифровать)")))
                             .clicked()
                         {
-                            let grille = Grille::new(*key);
-                            let mut out = String::new();
-
-                            for square in input_text
-                                .chars()
-                                .filter(|c| c.is_ascii_alphabetic())
-                                .map(|c| c.to_ascii_uppercase())
-                                .chunks(4 * 4)
-                                .into_iter()
+                            match Grille::new(*key) {
+                                Ok(grille) => *output_text = grille.encrypt_text(input_text),
+                                Err(e) => self.notifications.warning(e.to_string()),
+                            }
+                        }
+
+                        if ui.button(tr("Загрузить...")).clicked() {
+                            if let Err(e) = output_file_read_path_dialog.open_single_file(None) {
+                                self.notifications
+                                    .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                            }
+                        }
+
+                        if ui.button(tr("Сохранить...")).clicked() {
+                            if let Err(e) =
+                                output_file_write_path_dialog.show_save_single_file(None)
                             {
-                                let encrypted = grille.encrypt(&square.collect::<String>());
-                                out.push_str(
-                                    &encrypted
-                                        .map(|line| {
-                                            line.iter()
-                                                .map(|c| c.to_string())
-                                                .collect::<Vec<_>>()
-                                                .join(" ")
-                                        })
-                                        .join("\n"),
-                                );
-                                out.push_str("\n\n");
+                                self.notifications
+                                    .warning(trf("Не удалось открыть диалог: {}", &[&e]));
                             }
-                            *output_text = out;
                         }
 
-                        if ui.button("Загрузить...").clicked() {
-                            output_file_read_path_dialog
-                                .open_single_file(None)
-                                .expect("Unable to open file_path dialog");
+                        container.ui(
+                            ui,
+                            CipherKind::Grille,
+                            &[Stage::Grille { key: *key }],
+                            input_text,
+                            output_text,
+                            self.notifications,
+                        );
+                    });
+
+                    column[1].collapsing(AttackMethod::KeySearch.title(), |ui| {
+                        if attack.ui(ui, output_text, key, input_text) {
+                            self.notifications
+                                .info(tr("Применён найденный ключ решётки"));
+                        }
+                    });
+                    column[1].collapsing(
+                        tr("Ключ по известному открытому тексту"),
+                        |ui| {
+                            if infer.ui(ui, input_text, output_text, key, self.notifications) {
+                                live.edited(None, now);
+                            }
+                        },
+                    );
+                    column[1].collapsing(tr("Сравнение текстов"), |ui| {
+                        let stage = Stage::Grille { key: *key };
+                        compare.ui(
+                            ui,
+                            input_text,
+                            |text| stage.decrypt(&stage.encrypt(text)?),
+                            self.notifications,
+                        );
+                    });
+                });
+
+                let source_len = match live.direction {
+                    Direction::Encrypt => input_text.len(),
+                    Direction::Decrypt => output_text.len(),
+                };
+                let result = live.update(ui.ctx(), source_len, |direction| {
+                    let grille = Grille::new(*key).ok()?;
+                    let job: Job = match direction {
+                        Direction::Encrypt => {
+                            let text = input_text.clone();
+                            Box::new(move || Ok(grille.encrypt_text(&text)))
+                        }
+                        Direction::Decrypt => {
+                            let text = output_text.clone();
+                            Box::new(move || Ok(grille.decrypt_blocks(&text)))
                         }
+                    };
+                    Some(job)
+                });
+                let result = container.finish_live(output_text, result);
+                apply_live_result(
+                    ui.ctx(),
+                    result,
+                    input_text,
+                    output_text,
+                    self.notifications,
+                );
+            }
+            EncryptTab::Pipeline {
+                input_file_read_path_dialog,
+                input_file_write_path_dialog,
+                output_file_read_path_dialog,
+                output_file_write_path_dialog,
+                definition_read_path_dialog,
+                definition_write_path_dialog,
+
+                input_text,
+                output_text,
+                pipeline,
+                encoding,
+                live,
+                tools,
+                ..
+            } => {
+                let TabTools {
+                    container,
+                    compare,
+                    report,
+                    ..
+                } = tools;
+                let now = ui.input(|i| i.time);
 
-                        if ui.button("Сохранить...").clicked() {
-                            output_file_write_path_dialog
-                                .show_save_single_file(None)
-                                .expect("Unable to open file_path dialog");
+                if poll_load_dialog(
+                    input_file_read_path_dialog,
+                    input_text,
+                    *encoding,
+                    self.notifications,
+                ) {
+                    live.edited(Some(Direction::Encrypt), now);
+                }
+                poll_save_dialog(
+                    input_file_write_path_dialog,
+                    input_text,
+                    *encoding,
+                    self.notifications,
+                );
+
+                if poll_load_dialog(
+                    output_file_read_path_dialog,
+                    output_text,
+                    *encoding,
+                    self.notifications,
+                ) {
+                    live.edited(Some(Direction::Decrypt), now);
+                }
+                poll_save_dialog(
+                    output_file_write_path_dialog,
+                    output_text,
+                    *encoding,
+                    self.notifications,
+                );
+
+                match definition_read_path_dialog.check() {
+                    Some(Ok(Some(path))) => match Pipeline::load(&path) {
+                        Ok(loaded) => {
+                            *pipeline = loaded;
+                            live.edited(None, now);
+                            self.notifications
+                                .info(trf("Загружен конвейер {}", &[&path.display()]));
                         }
+                        Err(e) => self
+                            .notifications
+                            .error(trf("Не удалось загрузить {}: {}", &[&path.display(), &e])),
+                    },
+                    Some(Err(e)) => self
+                        .notifications
+                        .error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+                    _ => {}
+                }
+                match definition_write_path_dialog.check() {
+                    Some(Ok(Some(path))) => match pipeline.save(&path) {
+                        Ok(()) => self
+                            .notifications
+                            .info(trf("Конвейер сохранён в {}", &[&path.display()])),
+                        Err(e) => self
+                            .notifications
+                            .error(trf("Не удалось записать {}: {}", &[&path.display(), &e])),
+                    },
+                    Some(Err(e)) => self
+                        .notifications
+                        .error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+                    _ => {}
+                }
+
+                ui.horizontal(|ui| {
+                    encoding_combo(ui, "pipeline_encoding", encoding);
+                    ui.separator();
+                    live_controls(ui, live);
+                    ui.separator();
+                    if ui.button(tr("Загрузить конвейер...")).clicked() {
+                        if let Err(e) = definition_read_path_dialog.open_single_file(None) {
+                            self.notifications
+                                .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                        }
+                    }
+                    if ui.button(tr("Сохранить конвейер...")).clicked() {
+                        if let Err(e) = definition_write_path_dialog.show_save_single_file(None) {
+                            self.notifications
+                                .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                        }
+                    }
+                    ui.separator();
+                    report.ui(ui, self.notifications, || Report {
+                        title,
+                        stages: pipeline.stages.clone(),
+                        plaintext: input_text.clone(),
+                        ciphertext: output_text.clone(),
+                        attacks: Vec::new(),
+                    });
+                });
+
+                ui.columns(2, |column| {
+                    column[0].group(|ui| {
+                        ui.label(tr("Этапы (шифрование сверху вниз)"));
+                        egui::ScrollArea::vertical()
+                            .id_source("pipeline_stages")
+                            .max_height(300.0)
+                            .show(ui, |ui| {
+                                if pipeline_editor(ui, pipeline) {
+                                    live.edited(None, now);
+                                }
+                            });
                     });
+                    column[0].group(|ui| {
+                        ui.label(tr("Открытый текст"));
+                        if ui.text_edit_multiline(input_text).changed() {
+                            live.edited(Some(Direction::Encrypt), now);
+                        }
+
+                        if ui.button(tr("Получить (расшифровать)")).clicked() {
+                            match pipeline.decrypt(output_text) {
+                                Ok(text) => {
+                                    *input_text = container.finish(output_text, text);
+                                    container.check(
+                                        output_text,
+                                        input_text,
+                                        &pipeline.stages,
+                                        self.notifications,
+                                    );
+                                }
+                                Err(e) => self.notifications.warning(e.to_string()),
+                            }
+                        }
+
+                        ui.horizontal(|ui| {
+                            if ui.button(tr("Загрузить...")).clicked() {
+                                if let Err(e) = input_file_read_path_dialog.open_single_file(None) {
+                                    self.notifications
+                                        .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                                }
+                            }
+
+                            if ui.button(tr("Сохранить...")).clicked() {
+                                if let Err(e) =
+                                    input_file_write_path_dialog.show_save_single_file(None)
+                                {
+                                    self.notifications
+                                        .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                                }
+                            }
+                        });
+                    });
+
+                    column[1].group(|ui| {
+                        ui.label(tr("Зашифрованный текст"));
+                        if ui.text_edit_multiline(output_text).changed() {
+                            live.edited(Some(Direction::Decrypt), now);
+                        }
+
+                        if ui.button(tr("Получить (зашифровать)")).clicked() {
+                            match pipeline.encrypt(input_text) {
+                                Ok(text) => *output_text = text,
+                                Err(e) => self.notifications.warning(e.to_string()),
+                            }
+                        }
+
+                        ui.horizontal(|ui| {
+                            if ui.button(tr("Загрузить...")).clicked() {
+                                if let Err(e) = output_file_read_path_dialog.open_single_file(None)
+                                {
+                                    self.notifications
+                                        .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                                }
+                            }
+
+                            if ui.button(tr("Сохранить...")).clicked() {
+                                if let Err(e) =
+                                    output_file_write_path_dialog.show_save_single_file(None)
+                                {
+                                    self.notifications
+                                        .warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                                }
+                            }
+                            ui.separator();
+                            container.ui(
+                                ui,
+                                CipherKind::Pipeline,
+                                &pipeline.stages,
+                                input_text,
+                                output_text,
+                                self.notifications,
+                            );
+                        });
+                    });
+                });
+                ui.collapsing(tr("Сравнение текстов"), |ui| {
+                    compare.ui(
+                        ui,
+                        input_text,
+                        |text| pipeline.decrypt(&pipeline.encrypt(text)?),
+                        self.notifications,
+                    );
                 });
+
+                let source_len = match live.direction {
+                    Direction::Encrypt => input_text.len(),
+                    Direction::Decrypt => output_text.len(),
+                };
+                let result = live.update(ui.ctx(), source_len, |direction| {
+                    pipeline.validate().ok()?;
+                    let pipeline = pipeline.clone();
+                    let job: Job = match direction {
+                        Direction::Encrypt => {
+                            let text = input_text.clone();
+                            Box::new(move || pipeline.encrypt(&text))
+                        }
+                        Direction::Decrypt => {
+                            let text = output_text.clone();
+                            Box::new(move || pipeline.decrypt(&text))
+                        }
+                    };
+                    Some(job)
+                });
+                let result = container.finish_live(output_text, result);
+                apply_live_result(
+                    ui.ctx(),
+                    result,
+                    input_text,
+                    output_text,
+                    self.notifications,
+                );
+            }
+            EncryptTab::Avalanche { panel, .. } => {
+                egui::ScrollArea::vertical()
+                    .id_source("avalanche_scroll")
+                    .show(ui, |ui| panel.ui(ui, self.notifications));
             }
         }
     }
 
     fn title(&mut self, tab: &mut Self::Tab) -> egui::WidgetText {
-        match tab {
-            EncryptTab::Vigener { .. } => "Виженер".into(),
-            EncryptTab::Grille { .. } => "Вращающаяся решётка".into(),
+        tab.title().into()
+    }
+
+    fn context_menu(&mut self, ui: &mut egui::Ui, tab: &mut Self::Tab) {
+        ui.horizontal(|ui| {
+            ui.label(tr("Название:"));
+            let hint = tab.kind_title();
+            ui.add(egui::TextEdit::singleline(tab.name_mut()).hint_text(hint));
+        });
+        if ui.button(tr("Дублировать")).clicked() {
+            self.duplicated_tabs.push(tab.duplicate());
+            ui.close_menu();
+        }
+    }
+
+    fn on_tab_button(&mut self, tab: &mut Self::Tab, response: &egui::Response) {
+        response.clone().on_hover_text(tab.kind_title());
+    }
+
+    fn add_popup(&mut self, ui: &mut egui::Ui, node: NodeIndex) {
+        ui.set_min_width(150.0);
+        if ui.button(tr("Виженер")).clicked() {
+            self.added_tabs.push((node, EncryptTab::new_vigener()));
+        }
+        if ui.button(tr("Вращающаяся решётка")).clicked() {
+            self.added_tabs.push((node, EncryptTab::new_grille()));
+        }
+        if ui.button(tr("Каскад шифров")).clicked() {
+            self.added_tabs.push((node, EncryptTab::new_pipeline()));
+        }
+        if ui.button(tr("Лавинный эффект")).clicked() {
+            self.added_tabs.push((node, EncryptTab::new_avalanche()));
         }
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 struct MyTabs {
     tree: Tree<EncryptTab>,
+    #[serde(skip)]
+    container_dialog: ImNativeFileDialog<Option<PathBuf>>,
 }
 
 impl MyTabs {
     pub fn new() -> Self {
-        let tab1 = EncryptTab::Grille {
-            input_file_read_path_dialog: Default::default(),
-            input_file_write_path_dialog: Default::default(),
-            output_file_read_path_dialog: Default::default(),
-            output_file_write_path_dialog: Default::default(),
+        let tree = Tree::new(vec![EncryptTab::new_grille(), EncryptTab::new_vigener()]);
+        Self {
+            tree,
+            container_dialog: Default::default(),
+        }
+    }
 
-            input_text: String::new(),
-            output_text: String::new(),
-            key: [[false; 4]; 4],
-        };
-        let tab2 = EncryptTab::Vigener {
-            input_file_read_path_dialog: Default::default(),
-            input_file_write_path_dialog: Default::default(),
-            output_file_read_path_dialog: Default::default(),
-            output_file_write_path_dialog: Default::default(),
+    fn open(&mut self, tab: EncryptTab) {
+        self.tree.push_to_focused_leaf(tab);
+    }
 
-            input_text: String::new(),
-            output_text: String::new(),
-            key: String::new(),
+    fn duplicate_active(&mut self) {
+        if let Some((_, tab)) = self.tree.find_active_focused() {
+            let copy = tab.duplicate();
+            self.open(copy);
+        }
+    }
+
+    fn close_active(&mut self) {
+        let Some(node) = self.tree.focused_leaf() else {
+            return;
         };
+        if let Some(Node::Leaf { active, .. }) = self.tree.iter().nth(node.0) {
+            let active = *active;
+            self.tree.remove_tab((node, active));
+        }
+    }
+
+    fn menu(&mut self, ui: &mut egui::Ui, notifications: &mut Notifications) {
+        ui.menu_button(tr("Вкладки"), |ui| {
+            if ui.button(tr("Новая: Виженер")).clicked() {
+                self.open(EncryptTab::new_vigener());
+                ui.close_menu();
+            }
+            if ui.button(tr("Новая: Вращающаяся решётка")).clicked() {
+                self.open(EncryptTab::new_grille());
+                ui.close_menu();
+            }
+            if ui.button(tr("Новая: Каскад шифров")).clicked() {
+                self.open(EncryptTab::new_pipeline());
+                ui.close_menu();
+            }
+            if ui.button(tr("Новая: Лавинный эффект")).clicked() {
+                self.open(EncryptTab::new_avalanche());
+                ui.close_menu();
+            }
+            if ui.button(tr("Открыть контейнер...")).clicked() {
+                if let Err(e) = self.container_dialog.open_single_file(None) {
+                    notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                }
+                ui.close_menu();
+            }
+            ui.separator();
+            if ui.button(tr("Дублировать текущую")).clicked() {
+                self.duplicate_active();
+                ui.close_menu();
+            }
+            if ui.button(tr("Закрыть текущую")).clicked() {
+                self.close_active();
+                ui.close_menu();
+            }
+        });
+    }
+
+    fn texts_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        self.tree
+            .iter_mut()
+            .filter_map(|node| match node {
+                Node::Leaf { tabs, .. } => Some(tabs),
+                _ => None,
+            })
+            .flatten()
+            .flat_map(|tab| match tab {
+                EncryptTab::Vigener {
+                    input_text,
+                    output_text,
+                    ..
+                }
+                | EncryptTab::Grille {
+                    input_text,
+                    output_text,
+                    ..
+                }
+                | EncryptTab::Pipeline {
+                    input_text,
+                    output_text,
+                    ..
+                } => vec![input_text, output_text],
+                EncryptTab::Avalanche { panel, .. } => vec![panel.plaintext_mut()],
+            })
+    }
 
-        let tree = Tree::new(vec![tab1, tab2]);
-        Self { tree }
+    fn poll_container_dialog(&mut self, notifications: &mut Notifications) {
+        match self.container_dialog.check() {
+            Some(Ok(Some(path))) => match Container::load(&path) {
+                Ok(container) => {
+                    notifications.info(trf(
+                        "Открыт контейнер {}: {}",
+                        &[&path.display(), &container.cipher.name()],
+                    ));
+                    let title = path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    self.open(EncryptTab::from_container(container, title));
+                }
+                Err(e) => {
+                    notifications.error(trf("Не удалось открыть {}: {}", &[&path.display(), &e]))
+                }
+            },
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
     }
 
-    fn ui(&mut self, ui: &mut egui::Ui) {
-        let style = egui_dock::Style::from_egui(ui.style().as_ref());
+    fn ui(&mut self, ui: &mut egui::Ui, notifications: &mut Notifications) {
+        self.poll_container_dialog(notifications);
+        let mut style = egui_dock::Style::from_egui(ui.style().as_ref());
+        style.show_add_buttons = true;
+        style.show_add_popup = true;
+        let mut viewer = TabViewer {
+            notifications,
+            added_tabs: Vec::new(),
+            duplicated_tabs: Vec::new(),
+        };
         egui_dock::DockArea::new(&mut self.tree)
             .style(style)
-            .show_inside(ui, &mut TabViewer {});
+            .show_inside(ui, &mut viewer);
+
+        for (node, tab) in viewer.added_tabs {
+            self.tree.set_focused_node(node);
+            self.tree.push_to_focused_leaf(tab);
+        }
+        for tab in viewer.duplicated_tabs {
+            self.open(tab);
+        }
     }
 }
 
 use eframe::egui;
 
 fn main() -> Result<(), eframe::Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let options = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(1280.0, 720.0)),
         ..Default::default()
@@ -391,40 +1489,128 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Теория информации #1",
         options,
-        Box::new(|_cc| Box::<MyApp>::default()),
+        Box::new(|cc| Box::new(MyApp::new(cc))),
     )
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 struct MyApp {
     tabs: MyTabs,
+    persist_texts: bool,
+    settings: Settings,
+    /* Окна инструментов восстанавливаются открытыми или закрытыми вместе с
+     * параметрами; загруженные файлы и модели живут до закрытия программы */
+    show_settings: bool,
+    show_models: bool,
+    models: ModelsWindow,
+    show_exercises: bool,
+    exercises: ExercisesWindow,
+    #[serde(skip)]
+    applied_settings: Option<Settings>,
+    #[serde(skip)]
+    notifications: Notifications,
 }
 
 impl Default for MyApp {
     fn default() -> Self {
         Self {
             tabs: MyTabs::new(),
+            persist_texts: true,
+            settings: Settings::default(),
+            show_settings: false,
+            show_models: false,
+            models: ModelsWindow::default(),
+            show_exercises: false,
+            exercises: ExercisesWindow::default(),
+            applied_settings: None,
+            notifications: Notifications::default(),
         }
     }
 }
 
+impl MyApp {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        cc.storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default()
+    }
+
+    fn reset_workspace(&mut self) {
+        self.tabs = MyTabs::new();
+        self.notifications.info(tr("Рабочая область сброшена"));
+    }
+
+    fn menu_bar(&mut self, ui: &mut egui::Ui) {
+        egui::menu::bar(ui, |ui| {
+            self.tabs.menu(ui, &mut self.notifications);
+            ui.menu_button(tr("Рабочая область"), |ui| {
+                ui.checkbox(
+                    &mut self.persist_texts,
+                    tr("Сохранять тексты между запусками"),
+                );
+                ui.separator();
+                if ui.button(tr("Сбросить рабочую область")).clicked() {
+                    self.reset_workspace();
+                    ui.close_menu();
+                }
+            });
+            if ui.button(tr("Языковые модели")).clicked() {
+                self.show_models = !self.show_models;
+            }
+            if ui.button(tr("Генератор заданий")).clicked() {
+                self.show_exercises = !self.show_exercises;
+            }
+            if ui.button(tr("Настройки")).clicked() {
+                self.show_settings = !self.show_settings;
+            }
+        });
+    }
+}
+
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let mut fonts = egui::FontDefinitions::default();
-        fonts.font_data.insert(
-            "my_font".to_owned(),
-            egui::FontData::from_static(include_bytes!("../AnonymousPro-Bold.ttf")),
-        ); // .ttf and .otf supported
-        fonts
-            .families
-            .get_mut(&egui::FontFamily::Proportional)
-            .unwrap()
-            .insert(0, "my_font".to_owned());
-        ctx.set_fonts(fonts);
-        ctx.set_pixels_per_point(2.5);
-
-        ctx.set_visuals(eframe::egui::Visuals::light());
+        if self.applied_settings.as_ref() != Some(&self.settings) {
+            self.settings.apply(ctx);
+            self.applied_settings = Some(self.settings.clone());
+        }
+
+        self.notifications.begin_frame(ctx);
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            self.menu_bar(ui);
+        });
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            self.notifications.status_bar(ui);
+        });
         egui::CentralPanel::default().show(ctx, |ui| {
-            self.tabs.ui(ui);
+            self.tabs.ui(ui, &mut self.notifications);
         });
+        egui::Window::new(tr("Настройки"))
+            .id(egui::Id::new("settings_window"))
+            .open(&mut self.show_settings)
+            .resizable(false)
+            .show(ctx, |ui| self.settings.ui(ui));
+        egui::Window::new(tr("Языковые модели"))
+            .id(egui::Id::new("models_window"))
+            .open(&mut self.show_models)
+            .show(ctx, |ui| self.models.ui(ui, &mut self.notifications));
+        egui::Window::new(tr("Генератор заданий"))
+            .id(egui::Id::new("exercises_window"))
+            .open(&mut self.show_exercises)
+            .resizable(false)
+            .show(ctx, |ui| self.exercises.ui(ui, &mut self.notifications));
+        self.notifications.show(ctx);
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        if self.persist_texts {
+            eframe::set_value(storage, eframe::APP_KEY, self);
+        } else {
+            let texts: Vec<String> = self.tabs.texts_mut().map(std::mem::take).collect();
+            eframe::set_value(storage, eframe::APP_KEY, self);
+            for (slot, text) in self.tabs.texts_mut().zip(texts) {
+                *slot = text;
+            }
+        }
     }
 }
@@ -0,0 +1,194 @@
+/* Уведомления: всплывающие сообщения, строка состояния и журнал */
+
+use crate::i18n::{tr, trf};
+use eframe::egui;
+use std::time::Duration;
+
+const TOAST_SECONDS: f64 = 4.0;
+const MAX_LOG_LEN: usize = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn color(self, visuals: &egui::Visuals) -> egui::Color32 {
+        match self {
+            Level::Info => visuals.text_color(),
+            Level::Warning => visuals.warn_fg_color,
+            Level::Error => visuals.error_fg_color,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub level: Level,
+    pub text: String,
+    pub time: f64,
+}
+
+#[derive(Default)]
+pub struct Notifications {
+    log: Vec<Message>,
+    toasts: Vec<Message>,
+    now: f64,
+    pub show_log: bool,
+}
+
+impl Notifications {
+    pub fn push(&mut self, level: Level, text: impl Into<String>) {
+        let message = Message {
+            level,
+            text: text.into(),
+            time: self.now,
+        };
+        self.toasts.push(message.clone());
+        self.log.push(message);
+        if self.log.len() > MAX_LOG_LEN {
+            self.log.remove(0);
+        }
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(Level::Info, text);
+    }
+
+    pub fn warning(&mut self, text: impl Into<String>) {
+        self.push(Level::Warning, text);
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(Level::Error, text);
+    }
+
+    pub fn last(&self) -> Option<&Message> {
+        self.log.last()
+    }
+
+    pub fn clear(&mut self) {
+        self.log.clear();
+        self.toasts.clear();
+    }
+
+    fn advance(&mut self, now: f64) {
+        self.now = now;
+        self.toasts.retain(|t| now - t.time < TOAST_SECONDS);
+    }
+
+    /* Обновляет время и убирает устаревшие всплывающие сообщения */
+    pub fn begin_frame(&mut self, ctx: &egui::Context) {
+        self.advance(ctx.input(|i| i.time));
+        if !self.toasts.is_empty() {
+            ctx.request_repaint_after(Duration::from_millis(250));
+        }
+    }
+
+    pub fn status_bar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .selectable_label(self.show_log, trf("Журнал ({})", &[&self.log.len()]))
+                .clicked()
+            {
+                self.show_log = !self.show_log;
+            }
+            ui.separator();
+            if let Some(message) = self.last() {
+                let color = message.level.color(ui.visuals());
+                ui.label(egui::RichText::new(&message.text).color(color));
+            }
+        });
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        egui::Area::new("notifications_toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -40.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                for toast in &self.toasts {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        let color = toast.level.color(ui.visuals());
+                        ui.label(egui::RichText::new(&toast.text).color(color));
+                    });
+                }
+            });
+
+        let mut show_log = self.show_log;
+        let mut clear = false;
+        egui::Window::new(tr("Журнал"))
+            .id(egui::Id::new("notifications_log"))
+            .open(&mut show_log)
+            .default_size(egui::vec2(400.0, 200.0))
+            .show(ctx, |ui| {
+                if ui.button(tr("Очистить")).clicked() {
+                    clear = true;
+                }
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for message in &self.log {
+                            let color = message.level.color(ui.visuals());
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "[{:>7.1}] {}",
+                                    message.time, message.text
+                                ))
+                                .color(color),
+                            );
+                        }
+                    });
+            });
+        self.show_log = show_log;
+        if clear {
+            self.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_keeps_every_level_up_to_the_limit() {
+        let mut notifications = Notifications::default();
+        notifications.info("один");
+        notifications.warning("два");
+        notifications.error("три");
+        let levels: Vec<_> = notifications.log.iter().map(|m| m.level).collect();
+        assert_eq!(levels, [Level::Info, Level::Warning, Level::Error]);
+        assert_eq!(notifications.last().unwrap().text, "три");
+
+        for i in 0..MAX_LOG_LEN {
+            notifications.info(i.to_string());
+        }
+        assert_eq!(notifications.log.len(), MAX_LOG_LEN);
+        assert_eq!(notifications.log[0].text, "0");
+
+        notifications.clear();
+        assert!(notifications.last().is_none());
+        assert!(notifications.toasts.is_empty());
+    }
+
+    #[test]
+    fn toasts_expire_but_stay_in_the_log() {
+        let mut notifications = Notifications::default();
+        notifications.advance(10.0);
+        notifications.error("старое");
+        notifications.advance(12.0);
+        notifications.info("новое");
+        assert_eq!(notifications.toasts.len(), 2);
+
+        notifications.advance(10.0 + TOAST_SECONDS);
+        let texts: Vec<_> = notifications.toasts.iter().map(|t| &t.text[..]).collect();
+        assert_eq!(texts, ["новое"]);
+
+        notifications.advance(12.0 + TOAST_SECONDS);
+        assert!(notifications.toasts.is_empty());
+        assert_eq!(notifications.log.len(), 2);
+        assert_eq!(notifications.log[1].time, 12.0);
+    }
+}
@@ -0,0 +1,147 @@
+/* Ключи из парольной фразы на любом языке: PBKDF2-HMAC-SHA1 с отдельной солью для каждого шифра */
+
+use crate::i18n::tr;
+use crate::rotating_grille::{key_from_choices, CardboardMatrix};
+use crate::vigener_progressive::ALPHABET;
+use eframe::egui;
+use pbkdf2::pbkdf2_hmac;
+use sha1::Sha1;
+use unicode_normalization::UnicodeNormalization;
+
+/* Итерации замедляют перебор фраз по словарю, но вывод ключа остаётся мгновенным для человека */
+const ITERATIONS: u32 = 4096;
+const VIGENER_SALT: &[u8] = b"rotating_net/vigener";
+const GRILLE_SALT: &[u8] = b"rotating_net/grille";
+const VIGENER_KEY_LEN: usize = 32;
+
+/* Одна и та же фраза может прийти в составной (é) или разложенной (e + ◌́) записи,
+ * смотря по раскладке и системе; ключ выводится из NFC */
+fn phrase_bytes(passphrase: &str) -> Vec<u8> {
+    passphrase.nfc().collect::<String>().into_bytes()
+}
+
+fn pbkdf2_sha1(password: &[u8], salt: &[u8], len: usize) -> Vec<u8> {
+    let mut output = vec![0; len];
+    pbkdf2_hmac::<Sha1>(password, salt, ITERATIONS, &mut output);
+    output
+}
+
+/* Буквы ключа Виженера; байты от 231 = 7·33 отбрасываются, чтобы буквы были равновероятны */
+pub fn vigener_key(passphrase: &str) -> Option<String> {
+    if passphrase.is_empty() {
+        return None;
+    }
+    let mut len = 2 * VIGENER_KEY_LEN;
+    loop {
+        let bytes = pbkdf2_sha1(&phrase_bytes(passphrase), VIGENER_SALT, len);
+        let key: String = bytes
+            .iter()
+            .filter(|&&b| b < 231)
+            .map(|&b| ALPHABET[b as usize % ALPHABET.len()])
+            .take(VIGENER_KEY_LEN)
+            .collect();
+        if key.chars().count() == VIGENER_KEY_LEN {
+            return Some(key);
+        }
+        len *= 2;
+    }
+}
+
+/* В каждой из четырёх орбит выбирается одна из четырёх клеток */
+pub fn grille_key(passphrase: &str) -> Option<CardboardMatrix> {
+    if passphrase.is_empty() {
+        return None;
+    }
+    let bytes = pbkdf2_sha1(&phrase_bytes(passphrase), GRILLE_SALT, 4);
+    let choices = [0, 1, 2, 3].map(|i| bytes[i] as usize % 4);
+    Some(key_from_choices(&choices))
+}
+
+/* Поле для фразы с кнопкой вывода ключа; фраза не сохраняется между запусками */
+#[derive(Default)]
+pub struct PassphrasePanel {
+    phrase: String,
+    visible: bool,
+}
+
+impl PassphrasePanel {
+    /* Возвращает фразу, если пользователь попросил вывести из неё ключ */
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<&str> {
+        let mut derive = false;
+        ui.horizontal(|ui| {
+            ui.label(tr("Парольная фраза:"));
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.phrase)
+                    .password(!self.visible)
+                    .hint_text(tr("на любом языке")),
+            );
+            derive |= response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            ui.checkbox(&mut self.visible, tr("Показать"));
+            derive |= ui
+                .add_enabled(
+                    !self.phrase.is_empty(),
+                    egui::Button::new(tr("Вывести ключ")),
+                )
+                .on_hover_text(tr("Ключ получается из фразы через PBKDF2-HMAC-SHA1"))
+                .clicked();
+        });
+        (derive && !self.phrase.is_empty()).then_some(self.phrase.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotating_grille::is_valid_key;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /* Ключи, выведенные раньше, должны выводиться так же и после обновления библиотек */
+    #[test]
+    fn derived_keys_are_stable() {
+        assert_eq!(
+            hex(&pbkdf2_sha1(b"password", b"salt", 20)),
+            "4b007901b765489abead49d926f721d065a429c1"
+        );
+        /* Ё записана и одной буквой, и как Е с умлаутом */
+        for phrase in ["Ёжик в тумане", "Е\u{308}жик в тумане"] {
+            assert_eq!(
+                vigener_key(phrase).unwrap(),
+                "ЖДХЛЫЖДЦЛОЛКЕЦДРВНВАЖЬПЕСЯШЧХЗФГ"
+            );
+            let key = grille_key(phrase).unwrap();
+            let holes: Vec<usize> = (0..16).filter(|i| key[i / 4][i % 4]).collect();
+            assert_eq!(holes, [1, 2, 10, 12]);
+        }
+    }
+
+    #[test]
+    fn keys_are_deterministic_and_valid() {
+        for phrase in [
+            "correct horse battery staple",
+            "Мороз и солнце",
+            "東京タワー",
+        ] {
+            let key = vigener_key(phrase).unwrap();
+            assert_eq!(key.chars().count(), VIGENER_KEY_LEN);
+            assert!(key.chars().all(|c| ALPHABET.contains(&c)));
+            assert_eq!(vigener_key(phrase).unwrap(), key);
+            assert!(is_valid_key(&grille_key(phrase).unwrap()));
+        }
+        assert_ne!(vigener_key("фраза"), vigener_key("фраза "));
+        assert_eq!(vigener_key(""), None);
+        assert_eq!(grille_key(""), None);
+    }
+
+    #[test]
+    fn composed_and_decomposed_phrases_agree() {
+        let composed = "caf\u{e9} cr\u{e8}me";
+        let decomposed = "cafe\u{301} cre\u{300}me";
+        assert_ne!(composed, decomposed);
+        assert_eq!(vigener_key(composed), vigener_key(decomposed));
+        assert_eq!(grille_key(composed), grille_key(decomposed));
+        assert_ne!(vigener_key(composed), vigener_key("cafe creme"));
+    }
+}
@@ -0,0 +1,288 @@
+/* Каскад шифров: этапы применяются по порядку при шифровании и в обратном порядке при расшифровании */
+
+use crate::error::CipherError;
+use crate::grille_key::{grille_key_hints, GrilleKeyEditor};
+use crate::i18n::tr;
+use crate::rotating_grille::*;
+use crate::vigener_progressive::*;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stage {
+    Vigener {
+        key: String,
+        #[serde(default)]
+        alphabet_key: String,
+    },
+    Grille {
+        #[serde(with = "key_rows")]
+        key: CardboardMatrix,
+    },
+}
+
+impl Stage {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Stage::Vigener { .. } => tr("Виженер"),
+            Stage::Grille { .. } => tr("Вращающаяся решётка"),
+        }
+    }
+
+    pub fn vigener(key: &str, alphabet_key: &str) -> Result<VigenerProgressive, CipherError> {
+        let vig = VigenerProgressive::new(key)?;
+        Ok(vig.with_alphabet(Alphabet::parse(alphabet_key)?))
+    }
+
+    pub fn encrypt(&self, text: &str) -> Result<String, CipherError> {
+        match self {
+            Stage::Vigener { key, alphabet_key } => {
+                Ok(Self::vigener(key, alphabet_key)?.encrypt(text))
+            }
+            Stage::Grille { key } => Ok(Grille::new(*key)?.encrypt_text(text)),
+        }
+    }
+
+    pub fn decrypt(&self, text: &str) -> Result<String, CipherError> {
+        match self {
+            Stage::Vigener { key, alphabet_key } => {
+                Ok(Self::vigener(key, alphabet_key)?.decrypt(text))
+            }
+            Stage::Grille { key } => Grille::new(*key)?.decrypt_text(text),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /* Проверяет ключи всех этапов до запуска */
+    pub fn validate(&self) -> Result<(), CipherError> {
+        if self.stages.is_empty() {
+            return Err(CipherError::EmptyPipeline);
+        }
+        for (i, stage) in self.stages.iter().enumerate() {
+            let checked = match stage {
+                Stage::Vigener { key, alphabet_key } => Stage::vigener(key, alphabet_key).map(drop),
+                Stage::Grille { key } => Grille::new(*key).map(drop),
+            };
+            checked.map_err(|e| e.in_stage(i + 1))?;
+        }
+        Ok(())
+    }
+
+    pub fn encrypt(&self, text: &str) -> Result<String, CipherError> {
+        self.validate()?;
+        let mut text = text.to_owned();
+        for (i, stage) in self.stages.iter().enumerate() {
+            text = stage.encrypt(&text).map_err(|e| e.in_stage(i + 1))?;
+        }
+        Ok(text)
+    }
+
+    /* Этап решётки дополняет последний блок случайными буквами до 16, и расшифровка их
+     * возвращает: сам шифртекст не знает длины открытого текста. Точно обращает каскад
+     * контейнер, в котором эта длина записана */
+    pub fn decrypt(&self, text: &str) -> Result<String, CipherError> {
+        self.validate()?;
+        let mut text = text.to_owned();
+        for (i, stage) in self.stages.iter().enumerate().rev() {
+            text = stage.decrypt(&text).map_err(|e| e.in_stage(i + 1))?;
+        }
+        Ok(text)
+    }
+
+    /* Определение конвейера хранится в формате RON */
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).unwrap()
+    }
+
+    pub fn from_ron(text: &str) -> io::Result<Self> {
+        ron::from_str(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CipherError> {
+        Ok(Self::from_ron(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CipherError> {
+        Ok(std::fs::write(path, self.to_ron())?)
+    }
+}
+
+enum Edit {
+    Up(usize),
+    Down(usize),
+    Remove(usize),
+}
+
+/* Список этапов с редакторами ключей; возвращает true, если конвейер изменился */
+pub fn pipeline_editor(ui: &mut egui::Ui, pipeline: &mut Pipeline) -> bool {
+    let mut changed = false;
+    let mut edit = None;
+    let count = pipeline.stages.len();
+
+    for (i, stage) in pipeline.stages.iter_mut().enumerate() {
+        ui.push_id(i, |ui| {
+            ui.group(|ui| {
+                ui.horizontal(|ui| {
+                    ui.strong(format!("{}. {}", i + 1, stage.title()));
+                    if ui
+                        .add_enabled(i > 0, egui::Button::new("⏶").small())
+                        .clicked()
+                    {
+                        edit = Some(Edit::Up(i));
+                    }
+                    if ui
+                        .add_enabled(i + 1 < count, egui::Button::new("⏷").small())
+                        .clicked()
+                    {
+                        edit = Some(Edit::Down(i));
+                    }
+                    if ui
+                        .small_button("✖")
+                        .on_hover_text(tr("Удалить этап"))
+                        .clicked()
+                    {
+                        edit = Some(Edit::Remove(i));
+                    }
+                });
+
+                match stage {
+                    Stage::Vigener { key, alphabet_key } => {
+                        ui.horizontal(|ui| {
+                            ui.label(tr("Ключ: "));
+                            changed |= ui.text_edit_singleline(key).changed();
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label(tr("Алфавит таблицы: "));
+                            changed |= ui.text_edit_singleline(alphabet_key).changed();
+                        });
+                    }
+                    Stage::Grille { key } => {
+                        ui.horizontal(|ui| {
+                            ui.allocate_ui(egui::vec2(120.0, 120.0), |ui| {
+                                changed |= ui.add(GrilleKeyEditor::new(key)).changed();
+                            });
+                            ui.vertical(|ui| grille_key_hints(ui, key));
+                        });
+                    }
+                }
+            });
+        });
+    }
+
+    match edit {
+        Some(Edit::Up(i)) => pipeline.stages.swap(i - 1, i),
+        Some(Edit::Down(i)) => pipeline.stages.swap(i, i + 1),
+        Some(Edit::Remove(i)) => {
+            pipeline.stages.remove(i);
+        }
+        None => {}
+    }
+    changed |= edit.is_some();
+
+    ui.horizontal(|ui| {
+        ui.label(tr("Добавить этап:"));
+        if ui.button(tr("Виженер")).clicked() {
+            pipeline.stages.push(Stage::Vigener {
+                key: String::new(),
+                alphabet_key: String::new(),
+            });
+            changed = true;
+        }
+        if ui.button(tr("Решётка")).clicked() {
+            pipeline.stages.push(Stage::Grille {
+                key: [[false; 4]; 4],
+            });
+            changed = true;
+        }
+    });
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn superencryption() -> Pipeline {
+        Pipeline {
+            stages: vec![
+                Stage::Vigener {
+                    key: "ключ".to_owned(),
+                    alphabet_key: String::new(),
+                },
+                Stage::Grille {
+                    key: key_from_choices(&[1, 3, 0, 2]),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn cascade_is_reversible() {
+        let pipeline = superencryption();
+        let plaintext = "ПРИВЕТМИРЭТОПРОВЕРКАКАСКАДАШИФРО";
+        assert_eq!(plaintext.chars().count() % 16, 0);
+
+        let encrypted = pipeline.encrypt(plaintext).unwrap();
+        assert_eq!(pipeline.decrypt(&encrypted).unwrap(), plaintext);
+
+        let vigener_only = VigenerProgressive::new("ключ").unwrap().encrypt(plaintext);
+        assert_ne!(grille_letters(&encrypted).collect::<String>(), vigener_only);
+    }
+
+    use crate::container::{CipherKind, Container};
+    use proptest::prelude::*;
+    proptest! {
+        #[test]
+        fn padding_is_the_only_difference(s in "\\p{Cyrillic}{0,60}") {
+            let pipeline = superencryption();
+            let plain: String = filter_russian(s.chars()).collect();
+            let decrypted = pipeline.decrypt(&pipeline.encrypt(&s).unwrap()).unwrap();
+            prop_assert!(decrypted.starts_with(&plain));
+            let letters = decrypted.chars().count();
+            prop_assert_eq!(letters, plain.chars().count().div_ceil(16) * 16);
+        }
+
+        #[test]
+        fn container_restores_exact_text(s in "\\p{Cyrillic}{0,60}") {
+            let pipeline = superencryption();
+            let ciphertext = pipeline.encrypt(&s).unwrap();
+            let container =
+                Container::seal(CipherKind::Pipeline, &pipeline.stages, &s, ciphertext, true)
+                    .unwrap();
+            let plain: String = filter_russian(s.chars()).collect();
+            prop_assert_eq!(container.open(&pipeline.stages).unwrap(), plain);
+        }
+    }
+
+    #[test]
+    fn definition_round_trip() {
+        let pipeline = superencryption();
+        let text = pipeline.to_ron();
+        assert!(text.contains('#'));
+        assert_eq!(Pipeline::from_ron(&text).unwrap(), pipeline);
+        assert!(Pipeline::from_ron("(stages: [Grille(key: [\"#\"])])").is_err());
+    }
+
+    #[test]
+    fn invalid_stage_is_reported() {
+        let mut pipeline = superencryption();
+        pipeline.stages.push(Stage::Grille {
+            key: [[false; 4]; 4],
+        });
+        let error = pipeline.encrypt("ТЕКСТ").unwrap_err();
+        assert!(error.to_string().starts_with("этап 3"));
+        assert!(matches!(
+            error,
+            CipherError::Stage { index: 3, ref source } if matches!(**source, CipherError::InvalidGrille)
+        ));
+        assert!(Pipeline::default().encrypt("ТЕКСТ").is_err());
+    }
+}
@@ -0,0 +1,525 @@
+/* Отчёт о лабораторной работе: шифр, ключ, тексты, статистика и результаты атак
+ * в Markdown или в самостоятельном HTML, который можно сразу печатать в PDF */
+
+use crate::card::{self, Sheet};
+use crate::container::StageKind;
+use crate::i18n::{self, tr, trf};
+use crate::notifications::Notifications;
+use crate::pipeline::Stage;
+use crate::rotating_grille::CardboardMatrix;
+use eframe::egui;
+use im_native_dialog::ImNativeFileDialog;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/* Сколько самых частых букв показывать в статистике */
+const TOP_LETTERS: usize = 5;
+/* Сторона решётки на схеме в HTML, в миллиметрах */
+const GRILLE_SIZE: f64 = 40.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /* По расширению файла; всё, кроме .html и .htm, — Markdown */
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm") => {
+                ReportFormat::Html
+            }
+            _ => ReportFormat::Markdown,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "markdown" | "md" => Some(ReportFormat::Markdown),
+            "html" => Some(ReportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/* Как был найден ключ; одно название и в интерфейсе, и в отчётах командной строки */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttackMethod {
+    Dictionary,
+    KeySearch,
+}
+
+impl AttackMethod {
+    pub fn title(self) -> &'static str {
+        match self {
+            AttackMethod::Dictionary => tr("Подбор ключа по словарю"),
+            AttackMethod::KeySearch => tr("Подбор ключа по шифртексту"),
+        }
+    }
+}
+
+/* Ключ, найденный атакой */
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttackResult {
+    pub method: AttackMethod,
+    pub key: String,
+    pub score: f64,
+    pub plaintext: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub title: String,
+    pub stages: Vec<Stage>,
+    pub plaintext: String,
+    pub ciphertext: String,
+    pub attacks: Vec<AttackResult>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStats {
+    pub letters: usize,
+    pub distinct: usize,
+    /* Вероятность совпадения двух случайно выбранных букв текста */
+    pub coincidence: f64,
+    pub top: Vec<(char, usize)>,
+}
+
+pub fn text_stats(text: &str) -> TextStats {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        *counts
+            .entry(c.to_uppercase().next().unwrap_or(c))
+            .or_default() += 1;
+    }
+    let letters: usize = counts.values().sum();
+    let pairs: usize = counts.values().map(|&n| n * n.saturating_sub(1)).sum();
+    let coincidence = if letters > 1 {
+        pairs as f64 / (letters * (letters - 1)) as f64
+    } else {
+        0.0
+    };
+    let mut top: Vec<(char, usize)> = counts.into_iter().collect();
+    top.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    TextStats {
+        letters,
+        distinct: top.len(),
+        coincidence,
+        top: top.into_iter().take(TOP_LETTERS).collect(),
+    }
+}
+
+/* Ключ решётки одной строкой для таблиц: строки через пробел, # — отверстие */
+pub fn grille_key_line(key: &CardboardMatrix) -> String {
+    crate::rotating_grille::key_rows::to_rows(key).join(" ")
+}
+
+/* Схема решётки псевдографикой ASCII: # — отверстие */
+pub fn grille_ascii(key: &CardboardMatrix) -> String {
+    let border = "+---".repeat(4) + "+\n";
+    let mut text = border.clone();
+    for row in key {
+        for &hole in row {
+            text.push_str(if hole { "| # " } else { "|   " });
+        }
+        text.push_str("|\n");
+        text.push_str(&border);
+    }
+    text
+}
+
+enum Block {
+    Heading(String),
+    Paragraph(String),
+    Preformatted(String),
+    Grille(CardboardMatrix),
+    Table(Vec<String>, Vec<Vec<String>>),
+}
+
+fn stats_rows(plaintext: &TextStats, ciphertext: &TextStats) -> Vec<Vec<String>> {
+    let top = |stats: &TextStats| {
+        stats
+            .top
+            .iter()
+            .map(|(c, n)| format!("{c} ({n})"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    vec![
+        vec![
+            tr("Букв").to_owned(),
+            plaintext.letters.to_string(),
+            ciphertext.letters.to_string(),
+        ],
+        vec![
+            tr("Различных букв").to_owned(),
+            plaintext.distinct.to_string(),
+            ciphertext.distinct.to_string(),
+        ],
+        vec![
+            tr("Индекс совпадений").to_owned(),
+            format!("{:.4}", plaintext.coincidence),
+            format!("{:.4}", ciphertext.coincidence),
+        ],
+        vec![
+            tr("Самые частые буквы").to_owned(),
+            top(plaintext),
+            top(ciphertext),
+        ],
+    ]
+}
+
+impl Report {
+    /* Расшифровка атакой сравнивается с открытым текстом в том виде, в каком его
+     * возвращают шифры этапов; дополнение решётки в конце не учитывается */
+    fn recovers_plaintext(&self, decrypted: &str) -> bool {
+        let normalize = |text: &str| {
+            self.stages
+                .iter()
+                .map(StageKind::of)
+                .fold(text.to_owned(), |text, stage| stage.normalize(&text))
+        };
+        let expected = normalize(&self.plaintext);
+        let decrypted = normalize(decrypted);
+        !expected.is_empty()
+            && decrypted.starts_with(&expected)
+            && decrypted.chars().count() - expected.chars().count() < 16
+    }
+
+    fn blocks(&self) -> Vec<Block> {
+        let mut blocks = vec![Block::Heading(tr("Шифр и ключ").to_owned())];
+        for (i, stage) in self.stages.iter().enumerate() {
+            let title = if self.stages.len() > 1 {
+                trf("Этап {}: {}", &[&(i + 1), &stage.title()])
+            } else {
+                stage.title().to_owned()
+            };
+            match stage {
+                Stage::Vigener { key, alphabet_key } => {
+                    blocks.push(Block::Paragraph(trf("{}, ключ «{}»", &[&title, key])));
+                    if !alphabet_key.is_empty() {
+                        blocks.push(Block::Paragraph(trf(
+                            "Алфавит таблицы: «{}»",
+                            &[alphabet_key],
+                        )));
+                    }
+                }
+                Stage::Grille { key } => {
+                    blocks.push(Block::Paragraph(trf("{}, ключ:", &[&title])));
+                    blocks.push(Block::Grille(*key));
+                }
+            }
+        }
+        blocks.push(Block::Heading(tr("Открытый текст").to_owned()));
+        blocks.push(Block::Preformatted(self.plaintext.clone()));
+        blocks.push(Block::Heading(tr("Зашифрованный текст").to_owned()));
+        blocks.push(Block::Preformatted(self.ciphertext.clone()));
+
+        blocks.push(Block::Heading(tr("Статистика").to_owned()));
+        blocks.push(Block::Table(
+            vec![
+                String::new(),
+                tr("Открытый текст").to_owned(),
+                tr("Зашифрованный текст").to_owned(),
+            ],
+            stats_rows(&text_stats(&self.plaintext), &text_stats(&self.ciphertext)),
+        ));
+
+        if !self.attacks.is_empty() {
+            blocks.push(Block::Heading(tr("Результаты атак").to_owned()));
+            blocks.push(Block::Table(
+                vec![
+                    tr("Метод").to_owned(),
+                    tr("Ключ").to_owned(),
+                    tr("Оценка").to_owned(),
+                    tr("Совпадает с открытым текстом").to_owned(),
+                ],
+                self.attacks
+                    .iter()
+                    .map(|attack| {
+                        let matches = self.recovers_plaintext(&attack.plaintext);
+                        vec![
+                            attack.method.title().to_owned(),
+                            attack.key.clone(),
+                            format!("{:.1}", attack.score),
+                            if matches { tr("да") } else { tr("нет") }.to_owned(),
+                        ]
+                    })
+                    .collect(),
+            ));
+            blocks.push(Block::Paragraph(
+                tr("Расшифровка лучшим ключом:").to_owned(),
+            ));
+            blocks.push(Block::Preformatted(self.attacks[0].plaintext.clone()));
+        }
+        blocks
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Html => self.to_html(),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut text = format!("# {}\n", markdown_escape(&self.title));
+        for block in self.blocks() {
+            text.push('\n');
+            match block {
+                Block::Heading(heading) => {
+                    text.push_str(&format!("## {}\n", markdown_escape(&heading)))
+                }
+                Block::Paragraph(paragraph) => {
+                    text.push_str(&markdown_escape(&paragraph));
+                    text.push('\n');
+                }
+                Block::Preformatted(content) => text.push_str(&fenced(&content)),
+                Block::Grille(key) => text.push_str(&fenced(&grille_ascii(&key))),
+                Block::Table(header, rows) => {
+                    let row = |cells: &[String]| {
+                        let cells: Vec<String> = cells
+                            .iter()
+                            .map(|cell| markdown_escape(cell).replace('\n', " "))
+                            .collect();
+                        format!("| {} |\n", cells.join(" | "))
+                    };
+                    text.push_str(&row(&header));
+                    text.push_str(&format!("|{}\n", "---|".repeat(header.len())));
+                    for cells in &rows {
+                        text.push_str(&row(cells));
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    pub fn to_html(&self) -> String {
+        let lang = i18n::locale().code();
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html lang=\"{lang}\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n\
+             <h1>{title}</h1>\n",
+            title = html_escape(&self.title),
+        );
+        for block in self.blocks() {
+            match block {
+                Block::Heading(heading) => {
+                    html.push_str(&format!("<h2>{}</h2>\n", html_escape(&heading)))
+                }
+                Block::Paragraph(paragraph) => {
+                    html.push_str(&format!("<p>{}</p>\n", html_escape(&paragraph)))
+                }
+                Block::Preformatted(content) => {
+                    html.push_str(&format!("<pre>{}</pre>\n", html_escape(&content)))
+                }
+                Block::Grille(key) => match card::to_svg(&key, Sheet::Card, GRILLE_SIZE) {
+                    Ok(svg) => html.push_str(&format!("<figure>\n{svg}</figure>\n")),
+                    /* Неправильный ключ не нарисовать картонкой, остаётся схема */
+                    Err(_) => html.push_str(&format!("<pre>{}</pre>\n", grille_ascii(&key))),
+                },
+                Block::Table(header, rows) => {
+                    html.push_str("<table>\n<tr>");
+                    for cell in &header {
+                        html.push_str(&format!("<th>{}</th>", html_escape(cell)));
+                    }
+                    html.push_str("</tr>\n");
+                    for cells in &rows {
+                        html.push_str("<tr>");
+                        for cell in cells {
+                            html.push_str(&format!("<td>{}</td>", html_escape(cell)));
+                        }
+                        html.push_str("</tr>\n");
+                    }
+                    html.push_str("</table>\n");
+                }
+            }
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+/* При печати в PDF фон блоков убирается, а заголовок не отрывается от своего раздела */
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; }
+pre { white-space: pre-wrap; word-break: break-all; background: #f4f4f4; padding: 0.5em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #999; padding: 0.2em 0.6em; text-align: left; }
+@media print {
+  body { margin: 0; max-width: none; }
+  pre { background: none; border: 1px solid #ccc; }
+  h2 { break-after: avoid; }
+  table, figure { break-inside: avoid; }
+}
+";
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]<>#|".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/* Блок кода, ограда которого длиннее любой последовательности ` внутри текста */
+fn fenced(content: &str) -> String {
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat((longest + 1).max(3));
+    let newline = if content.ends_with('\n') { "" } else { "\n" };
+    format!("{fence}text\n{content}{newline}{fence}\n")
+}
+
+/* Кнопка «Отчёт...»: содержимое берётся в момент нажатия, формат — по расширению файла */
+#[derive(Default)]
+pub struct ReportButton {
+    dialog: ImNativeFileDialog<Option<PathBuf>>,
+    pending: Option<Report>,
+}
+
+impl ReportButton {
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        notifications: &mut Notifications,
+        report: impl FnOnce() -> Report,
+    ) {
+        match self.dialog.check() {
+            Some(Ok(Some(path))) => {
+                if let Some(report) = self.pending.take() {
+                    let text = report.render(ReportFormat::from_path(&path));
+                    match std::fs::write(&path, text) {
+                        Ok(()) => {
+                            notifications.info(trf("Отчёт сохранён в {}", &[&path.display()]))
+                        }
+                        Err(e) => notifications
+                            .error(trf("Не удалось записать {}: {}", &[&path.display(), &e])),
+                    }
+                }
+            }
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
+
+        if ui
+            .button(tr("Отчёт..."))
+            .on_hover_text(tr(
+                "Сохранить отчёт: .md — Markdown, .html — HTML для печати",
+            ))
+            .clicked()
+        {
+            self.pending = Some(report());
+            if let Err(e) = self.dialog.show_save_single_file(None) {
+                notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotating_grille::key_from_choices;
+
+    fn sample() -> Report {
+        Report {
+            title: "Лабораторная <1>".to_owned(),
+            stages: vec![
+                Stage::Vigener {
+                    key: "КЛЮЧ".to_owned(),
+                    alphabet_key: String::new(),
+                },
+                Stage::Grille {
+                    key: key_from_choices(&[0, 1, 2, 3]),
+                },
+            ],
+            plaintext: "абба ```".to_owned(),
+            ciphertext: "ВГДЕ".to_owned(),
+            attacks: vec![AttackResult {
+                method: AttackMethod::Dictionary,
+                key: "КЛЮЧ".to_owned(),
+                score: -12.5,
+                plaintext: "АББАЖЭЦБЯЫКЁЪЩЙМ".to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn counts_letters_and_coincidence() {
+        let stats = text_stats("Абба, ba!");
+        assert_eq!(stats.letters, 6);
+        assert_eq!(stats.distinct, 4);
+        /* Латинские B и A отличаются от русских Б и А */
+        assert_eq!(stats.top[..2], [('А', 2), ('Б', 2)]);
+        /* Пары А-А и Б-Б из 15 возможных */
+        assert!((stats.coincidence - 2.0 / 15.0).abs() < 1e-12);
+        assert_eq!(text_stats("").coincidence, 0.0);
+    }
+
+    #[test]
+    fn attack_output_is_compared_after_normalization() {
+        let report = Report {
+            stages: vec![Stage::Vigener {
+                key: "КЛЮЧ".to_owned(),
+                alphabet_key: String::new(),
+            }],
+            plaintext: "Привет, Мир! Ёлки-палки.".to_owned(),
+            ..sample()
+        };
+        assert!(report.recovers_plaintext("ПРИВЕТМИРЁЛКИПАЛКИ"));
+        assert!(!report.recovers_plaintext("ПРИВЕТМИРЁЛКИПАЛК"));
+        assert!(!report.recovers_plaintext("ПРИВЕТМИРЁЛКИПАЛКА"));
+        /* Решётка дополняет текст до целого блока */
+        let grille = Report {
+            stages: vec![Stage::Grille {
+                key: key_from_choices(&[0, 1, 2, 3]),
+            }],
+            ..report
+        };
+        assert!(grille.recovers_plaintext("ПРИВЕТМИРЁЛКИПАЛКИЖЭЦБЯЫКЁЪЩЙМУФ"));
+        assert!(!grille.recovers_plaintext("ПРИВЕТМИРЁЛКИПАЛКИЖЭЦБЯЫКЁЪЩЙМУФЧШ"));
+        assert!(!grille.recovers_plaintext(""));
+    }
+
+    #[test]
+    fn markdown_keeps_texts_verbatim() {
+        let markdown = sample().render(ReportFormat::Markdown);
+        assert!(markdown.starts_with("# Лабораторная \\<1\\>\n"));
+        assert!(markdown.contains("````text\nабба ```\n````\n"));
+        assert!(markdown.contains("| # |"));
+        let row = format!(
+            "| {} | КЛЮЧ | -12.5 | {} |",
+            AttackMethod::Dictionary.title(),
+            tr("да")
+        );
+        assert!(markdown.contains(&row));
+    }
+
+    #[test]
+    fn html_is_escaped_and_has_svg() {
+        let html = sample().render(ReportFormat::Html);
+        assert!(html.contains("<h1>Лабораторная &lt;1&gt;</h1>"));
+        assert_eq!(html.matches("<svg").count(), 1);
+        assert_eq!(html.matches("fill=\"#a0a0a0\"").count(), 4);
+        assert_eq!(
+            ReportFormat::from_path(Path::new("отчёт.HTML")),
+            ReportFormat::Html
+        );
+        assert_eq!(
+            ReportFormat::from_path(Path::new("отчёт.md")),
+            ReportFormat::Markdown
+        );
+    }
+}
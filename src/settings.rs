@@ -0,0 +1,176 @@
+/* Настройки внешнего вида: шрифт, тема и масштаб */
+
+use crate::i18n::{self, tr, Locale, LOCALES};
+use eframe::egui;
+use egui::{FontFamily, FontId, TextStyle};
+use serde::{Deserialize, Serialize};
+
+const BUNDLED_FONT: &str = "anonymous_pro";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FontChoice {
+    AnonymousPro,
+    Proportional,
+    Monospace,
+}
+
+impl FontChoice {
+    fn name(self) -> &'static str {
+        match self {
+            FontChoice::AnonymousPro => "Anonymous Pro",
+            FontChoice::Proportional => tr("Стандартный"),
+            FontChoice::Monospace => tr("Моноширинный"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub font: FontChoice,
+    pub font_size: f32,
+    pub theme: Theme,
+    pub ui_scale: f32,
+    pub language: Locale,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            font: FontChoice::AnonymousPro,
+            font_size: 12.5,
+            theme: Theme::Light,
+            ui_scale: 2.5,
+            language: Locale::Russian,
+        }
+    }
+}
+
+impl Settings {
+    fn fonts(&self) -> egui::FontDefinitions {
+        let mut fonts = egui::FontDefinitions::default();
+        fonts.font_data.insert(
+            BUNDLED_FONT.to_owned(),
+            egui::FontData::from_static(include_bytes!("../AnonymousPro-Bold.ttf")),
+        );
+
+        let first = match self.font {
+            FontChoice::AnonymousPro => Some(BUNDLED_FONT.to_owned()),
+            FontChoice::Proportional => None,
+            FontChoice::Monospace => fonts
+                .families
+                .get(&FontFamily::Monospace)
+                .and_then(|family| family.first().cloned()),
+        };
+        if let Some(first) = first {
+            fonts
+                .families
+                .entry(FontFamily::Proportional)
+                .or_default()
+                .insert(0, first);
+        }
+        fonts
+    }
+
+    fn style(&self, ctx: &egui::Context) -> egui::Style {
+        let mut style = (*ctx.style()).clone();
+        style.visuals = match self.theme {
+            Theme::Light => egui::Visuals::light(),
+            Theme::Dark => egui::Visuals::dark(),
+        };
+
+        let size = self.font_size;
+        style.text_styles = [
+            (
+                TextStyle::Small,
+                FontId::new(size * 0.72, FontFamily::Proportional),
+            ),
+            (TextStyle::Body, FontId::new(size, FontFamily::Proportional)),
+            (
+                TextStyle::Button,
+                FontId::new(size, FontFamily::Proportional),
+            ),
+            (
+                TextStyle::Heading,
+                FontId::new(size * 1.44, FontFamily::Proportional),
+            ),
+            (
+                TextStyle::Monospace,
+                FontId::new(size, FontFamily::Monospace),
+            ),
+        ]
+        .into();
+        style
+    }
+
+    /* Дорогая операция: пересобирает шрифты, вызывать только при изменении */
+    pub fn apply(&self, ctx: &egui::Context) {
+        i18n::set_locale(self.language);
+        ctx.set_fonts(self.fonts());
+        ctx.set_style(self.style(ctx));
+        ctx.set_pixels_per_point(self.ui_scale);
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("settings_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label(tr("Язык интерфейса"));
+                ui.horizontal(|ui| {
+                    for language in LOCALES {
+                        ui.selectable_value(&mut self.language, language, language.native_name());
+                    }
+                });
+                ui.end_row();
+
+                ui.label(tr("Шрифт"));
+                egui::ComboBox::from_id_source("settings_font")
+                    .selected_text(self.font.name())
+                    .show_ui(ui, |ui| {
+                        for font in [
+                            FontChoice::AnonymousPro,
+                            FontChoice::Proportional,
+                            FontChoice::Monospace,
+                        ] {
+                            ui.selectable_value(&mut self.font, font, font.name());
+                        }
+                    });
+                ui.end_row();
+
+                ui.label(tr("Размер шрифта"));
+                ui.add(egui::Slider::new(&mut self.font_size, 8.0..=32.0).step_by(0.5));
+                ui.end_row();
+
+                ui.label(tr("Тема"));
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.theme, Theme::Light, tr("Светлая"));
+                    ui.selectable_value(&mut self.theme, Theme::Dark, tr("Тёмная"));
+                });
+                ui.end_row();
+
+                ui.label(tr("Масштаб"));
+                ui.horizontal(|ui| {
+                    ui.add(egui::Slider::new(&mut self.ui_scale, 0.75..=4.0).step_by(0.25));
+                    for (preset, text) in [
+                        (1.0, tr("Ноутбук")),
+                        (2.5, tr("Монитор")),
+                        (3.5, tr("Проектор")),
+                    ] {
+                        ui.selectable_value(&mut self.ui_scale, preset, text);
+                    }
+                });
+                ui.end_row();
+            });
+
+        ui.separator();
+        if ui.button(tr("По умолчанию")).clicked() {
+            *self = Settings::default();
+        }
+    }
+}
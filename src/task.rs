@@ -0,0 +1,95 @@
+/* Фоновая задача с отчётом о прогрессе и отменой */
+
+use eframe::egui;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::time::Duration;
+
+enum Message<P, R> {
+    Progress(P),
+    Done(R),
+}
+
+pub struct Reporter<P, R> {
+    cancel: Arc<AtomicBool>,
+    sender: Sender<Message<P, R>>,
+}
+
+impl<P, R> Reporter<P, R> {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn progress(&self, progress: P) {
+        let _ = self.sender.send(Message::Progress(progress));
+    }
+}
+
+pub struct Task<P, R> {
+    cancel: Arc<AtomicBool>,
+    receiver: Option<Receiver<Message<P, R>>>,
+    pub progress: Option<P>,
+}
+
+impl<P, R> Default for Task<P, R> {
+    fn default() -> Self {
+        Task {
+            cancel: Arc::new(AtomicBool::new(false)),
+            receiver: None,
+            progress: None,
+        }
+    }
+}
+
+impl<P: Send + 'static, R: Send + 'static> Task<P, R> {
+    /* Запускает работу в отдельном потоке; предыдущая задача отменяется */
+    pub fn spawn(&mut self, work: impl FnOnce(&Reporter<P, R>) -> R + Send + 'static) {
+        self.cancel();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = channel();
+        let reporter = Reporter {
+            cancel: cancel.clone(),
+            sender,
+        };
+        std::thread::spawn(move || {
+            let result = work(&reporter);
+            let _ = reporter.sender.send(Message::Done(result));
+        });
+
+        self.cancel = cancel;
+        self.receiver = Some(receiver);
+        self.progress = None;
+    }
+
+    pub fn cancel(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /* Забирает накопившийся прогресс; возвращает результат, когда задача завершилась */
+    pub fn poll(&mut self, ctx: &egui::Context) -> Option<R> {
+        let receiver = self.receiver.as_ref()?;
+        loop {
+            match receiver.try_recv() {
+                Ok(Message::Progress(progress)) => self.progress = Some(progress),
+                Ok(Message::Done(result)) => {
+                    self.receiver = None;
+                    return Some(result);
+                }
+                Err(TryRecvError::Empty) => {
+                    ctx.request_repaint_after(Duration::from_millis(100));
+                    return None;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    return None;
+                }
+            }
+        }
+    }
+}
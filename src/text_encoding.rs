@@ -0,0 +1,186 @@
+/* Определение и преобразование кодировок текстовых файлов */
+
+use encoding_rs::{Encoding, IBM866, KOI8_R, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1251};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1251,
+    Koi8R,
+    Cp866,
+}
+
+pub const ENCODINGS: [TextEncoding; 6] = [
+    TextEncoding::Utf8,
+    TextEncoding::Utf16Le,
+    TextEncoding::Utf16Be,
+    TextEncoding::Windows1251,
+    TextEncoding::Koi8R,
+    TextEncoding::Cp866,
+];
+
+/* Однобайтовые кодировки, среди которых выбирает эвристика */
+const LEGACY_CYRILLIC: [TextEncoding; 3] = [
+    TextEncoding::Windows1251,
+    TextEncoding::Koi8R,
+    TextEncoding::Cp866,
+];
+
+/* Строчные буквы в порядке убывания частоты в русских текстах */
+const RU_FREQUENCY_ORDER: &str = "оеаинтсрвлкмдпуяыьгзбчйхжшюцщэфъё";
+
+pub struct Decoded {
+    pub text: String,
+    pub encoding: TextEncoding,
+    pub had_errors: bool,
+}
+
+impl TextEncoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf16Le => "UTF-16LE",
+            TextEncoding::Utf16Be => "UTF-16BE",
+            TextEncoding::Windows1251 => "Windows-1251",
+            TextEncoding::Koi8R => "KOI8-R",
+            TextEncoding::Cp866 => "CP866",
+        }
+    }
+
+    fn encoding(self) -> &'static Encoding {
+        match self {
+            TextEncoding::Utf8 => UTF_8,
+            TextEncoding::Utf16Le => UTF_16LE,
+            TextEncoding::Utf16Be => UTF_16BE,
+            TextEncoding::Windows1251 => WINDOWS_1251,
+            TextEncoding::Koi8R => KOI8_R,
+            TextEncoding::Cp866 => IBM866,
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Decoded {
+        let (text, had_errors) = self.encoding().decode_with_bom_removal(bytes);
+        Decoded {
+            text: text.into_owned(),
+            encoding: self,
+            had_errors,
+        }
+    }
+
+    /* Возвращает байты и признак того, что часть символов не представима */
+    pub fn encode(self, text: &str) -> (Vec<u8>, bool) {
+        match self {
+            /* encoding_rs не кодирует в UTF-16, поэтому делаем это сами, с BOM */
+            TextEncoding::Utf16Le => {
+                let mut bytes = vec![0xFF, 0xFE];
+                bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+                (bytes, false)
+            }
+            TextEncoding::Utf16Be => {
+                let mut bytes = vec![0xFE, 0xFF];
+                bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+                (bytes, false)
+            }
+            _ => {
+                let (bytes, _, had_errors) = self.encoding().encode(text);
+                (bytes.into_owned(), had_errors)
+            }
+        }
+    }
+}
+
+fn cyrillic_score(text: &str) -> i64 {
+    text.chars()
+        .map(|c| {
+            if let Some(rank) = RU_FREQUENCY_ORDER.chars().position(|l| l == c) {
+                (RU_FREQUENCY_ORDER.chars().count() - rank) as i64
+            } else if ('А'..='Я').contains(&c) || c == 'Ё' {
+                1
+            } else if c.is_ascii() {
+                0
+            } else {
+                -20
+            }
+        })
+        .sum()
+}
+
+pub fn detect(bytes: &[u8]) -> TextEncoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return if encoding == UTF_16LE {
+            TextEncoding::Utf16Le
+        } else if encoding == UTF_16BE {
+            TextEncoding::Utf16Be
+        } else {
+            TextEncoding::Utf8
+        };
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        return TextEncoding::Utf8;
+    }
+
+    LEGACY_CYRILLIC
+        .into_iter()
+        .max_by_key(|encoding| cyrillic_score(&encoding.decode(bytes).text))
+        .unwrap_or(TextEncoding::Utf8)
+}
+
+pub fn decode_auto(bytes: &[u8]) -> Decoded {
+    detect(bytes).decode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "Съешь же ещё этих мягких французских булок, да выпей чаю.\n\
+                          Широкая электрификация южных губерний даст мощный толчок подъёму сельского хозяйства.";
+
+    #[test]
+    fn detects_legacy_cyrillic() {
+        for encoding in LEGACY_CYRILLIC {
+            let (bytes, had_errors) = encoding.encode(SAMPLE);
+            assert!(!had_errors);
+            let decoded = decode_auto(&bytes);
+            assert_eq!(decoded.encoding, encoding);
+            assert_eq!(decoded.text, SAMPLE);
+        }
+    }
+
+    #[test]
+    fn detects_unicode_with_bom() {
+        for encoding in [TextEncoding::Utf16Le, TextEncoding::Utf16Be] {
+            let (bytes, _) = encoding.encode(SAMPLE);
+            let decoded = decode_auto(&bytes);
+            assert_eq!(decoded.encoding, encoding);
+            assert_eq!(decoded.text, SAMPLE);
+        }
+
+        let mut bytes = vec![0xEF, 0xBB, 0xBF];
+        bytes.extend(SAMPLE.as_bytes());
+        let decoded = decode_auto(&bytes);
+        assert_eq!(decoded.encoding, TextEncoding::Utf8);
+        assert_eq!(decoded.text, SAMPLE);
+    }
+
+    #[test]
+    fn reports_unmappable_characters() {
+        let (_, had_errors) = TextEncoding::Koi8R.encode("Ключ: 鍵");
+        assert!(had_errors);
+    }
+
+    use proptest::prelude::*;
+    proptest! {
+        #[test]
+        fn utf8_round_trip(s in "\\PC*") {
+            let (bytes, _) = TextEncoding::Utf8.encode(&s);
+            let decoded = decode_auto(&bytes);
+            prop_assert_eq!(decoded.encoding, TextEncoding::Utf8);
+            prop_assert_eq!(decoded.text, s);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+/* Подбор ключа прогрессивного Виженера по словарю: каждое слово пробуется как ключ */
+
+use crate::i18n::{tr, trf};
+use crate::language_model::{model, Language, LanguageModel};
+use crate::notifications::Notifications;
+use crate::task::Task;
+use crate::text_encoding::decode_auto;
+use crate::vigener_progressive::*;
+use eframe::egui;
+use im_native_dialog::ImNativeFileDialog;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/* Для ранжирования хватает начала шифртекста, полностью расшифровываются только лучшие */
+const SAMPLE_LETTERS: usize = 400;
+/* Как часто потоки сообщают о прогрессе, в словах */
+const PROGRESS_STEP: usize = 1000;
+
+#[derive(Clone, Debug)]
+pub struct Hit {
+    pub key: String,
+    pub score: f64,
+    pub plaintext: String,
+}
+
+/* Слова словаря в виде ключей: только русские буквы, без повторов */
+pub fn parse_wordlist(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .map(|word| filter_russian(word.chars()).collect::<String>())
+        .filter(|word| !word.is_empty())
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+    words
+}
+
+fn insert_hit(hits: &mut Vec<Hit>, hit: Hit, top: usize) {
+    if hits.len() == top && hits.last().is_some_and(|last| last.score >= hit.score) {
+        return;
+    }
+    let position = hits.partition_point(|h| h.score >= hit.score);
+    hits.insert(position, hit);
+    hits.truncate(top);
+}
+
+/* Возвращает top лучших ключей по убыванию оценки.
+ * on_progress получает долю проверенных слов и возвращает true для остановки. */
+pub fn dictionary_attack(
+    ciphertext: &str,
+    words: &[String],
+    alphabet: Alphabet,
+    model: &LanguageModel,
+    top: usize,
+    threads: usize,
+    on_progress: impl Fn(f32) -> bool + Sync,
+) -> Vec<Hit> {
+    let sample: String = filter_russian(ciphertext.chars())
+        .take(SAMPLE_LETTERS)
+        .collect();
+    if sample.is_empty() || words.is_empty() || top == 0 {
+        return Vec::new();
+    }
+
+    let total = words.len() as f32;
+    let checked = AtomicUsize::new(0);
+    let chunk = words.len().div_ceil(threads.max(1));
+    let mut hits = Vec::new();
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = words
+            .chunks(chunk)
+            .map(|words| {
+                let (sample, checked, on_progress) = (&sample, &checked, &on_progress);
+                scope.spawn(move || {
+                    let mut hits = Vec::new();
+                    for (i, word) in words.iter().enumerate() {
+                        if i % PROGRESS_STEP == PROGRESS_STEP - 1 {
+                            let done = checked.fetch_add(PROGRESS_STEP, Ordering::Relaxed);
+                            if on_progress(done as f32 / total) {
+                                break;
+                            }
+                        }
+                        let Ok(vig) = VigenerProgressive::new(word) else {
+                            continue;
+                        };
+                        let vig = vig.with_alphabet(alphabet);
+                        let score = model.score(&vig.decrypt(sample));
+                        let hit = Hit {
+                            key: word.clone(),
+                            score,
+                            plaintext: String::new(),
+                        };
+                        insert_hit(&mut hits, hit, top);
+                    }
+                    hits
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            for hit in worker.join().unwrap() {
+                insert_hit(&mut hits, hit, top);
+            }
+        }
+    });
+
+    for hit in &mut hits {
+        if let Ok(vig) = VigenerProgressive::new(&hit.key) {
+            hit.plaintext = vig.with_alphabet(alphabet).decrypt(ciphertext);
+        }
+    }
+    hits
+}
+
+pub struct DictionaryPanel {
+    dialog: ImNativeFileDialog<Option<PathBuf>>,
+    words: Vec<String>,
+    source: Option<String>,
+    top: usize,
+    task: Task<f32, Vec<Hit>>,
+    hits: Vec<Hit>,
+}
+
+impl Default for DictionaryPanel {
+    fn default() -> Self {
+        DictionaryPanel {
+            dialog: Default::default(),
+            words: Vec::new(),
+            source: None,
+            top: 10,
+            task: Task::default(),
+            hits: Vec::new(),
+        }
+    }
+}
+
+impl DictionaryPanel {
+    pub fn hits(&self) -> &[Hit] {
+        &self.hits
+    }
+
+    /* Возвращает true, если пользователь применил найденный ключ */
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        ciphertext: &str,
+        key: &mut String,
+        alphabet: Alphabet,
+        plaintext: &mut String,
+        notifications: &mut Notifications,
+    ) -> bool {
+        if let Some(hits) = self.task.poll(ui.ctx()) {
+            self.hits = hits;
+        }
+
+        match self.dialog.check() {
+            Some(Ok(Some(path))) => match std::fs::read(&path) {
+                Ok(bytes) => {
+                    self.words = parse_wordlist(&decode_auto(&bytes).text);
+                    self.source = Some(path.display().to_string());
+                    notifications.info(trf(
+                        "Загружен словарь {}: {} слов",
+                        &[&path.display(), &self.words.len()],
+                    ));
+                }
+                Err(e) => {
+                    notifications.error(trf("Не удалось прочитать {}: {}", &[&path.display(), &e]))
+                }
+            },
+            Some(Err(e)) => notifications.error(trf("Ошибка диалога выбора файла: {}", &[&e])),
+            _ => {}
+        }
+
+        ui.horizontal(|ui| {
+            match &self.source {
+                Some(source) => {
+                    ui.label(trf("Словарь: {} ({} слов)", &[&source, &self.words.len()]))
+                }
+                None => ui.label(tr("Словарь не загружен")),
+            };
+            if ui.button(tr("Загрузить словарь...")).clicked() {
+                if let Err(e) = self.dialog.open_single_file(None) {
+                    notifications.warning(trf("Не удалось открыть диалог: {}", &[&e]));
+                }
+            }
+        });
+
+        if self.task.is_running() {
+            ui.horizontal(|ui| {
+                let fraction = self.task.progress.unwrap_or(0.0);
+                ui.add(egui::ProgressBar::new(fraction).show_percentage());
+                if ui.button(tr("Отмена")).clicked() {
+                    self.task.cancel();
+                }
+            });
+        } else {
+            ui.horizontal(|ui| {
+                ui.label(tr("Лучших:"));
+                ui.add(egui::DragValue::new(&mut self.top).clamp_range(1..=100));
+                let ready =
+                    !self.words.is_empty() && filter_russian(ciphertext.chars()).count() > 0;
+                if ui
+                    .add_enabled(ready, egui::Button::new(tr("Перебрать словарь")))
+                    .on_disabled_hover_text(tr("Нужны словарь и шифртекст"))
+                    .clicked()
+                {
+                    let ciphertext = ciphertext.to_owned();
+                    let words = self.words.clone();
+                    let top = self.top;
+                    let model = model(Language::Russian);
+                    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+                    self.hits.clear();
+                    self.task.spawn(move |reporter| {
+                        dictionary_attack(
+                            &ciphertext,
+                            &words,
+                            alphabet,
+                            &model,
+                            top,
+                            threads,
+                            |done| {
+                                reporter.progress(done);
+                                reporter.is_cancelled()
+                            },
+                        )
+                    });
+                }
+            });
+        }
+
+        let mut applied = false;
+        if !self.hits.is_empty() {
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("dictionary_hits")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for hit in &self.hits {
+                                ui.monospace(&hit.key);
+                                ui.label(format!("{:.1}", hit.score));
+                                ui.label(hit.plaintext.chars().take(40).collect::<String>());
+                                if ui.small_button(tr("Применить")).clicked() {
+                                    *key = hit.key.clone();
+                                    *plaintext = hit.plaintext.clone();
+                                    applied = true;
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAINTEXT: &str = "Вечером в библиотеке было тихо и тепло. За длинным столом сидели студенты и готовились к экзамену по теории информации.";
+
+    #[test]
+    fn finds_dictionary_word() {
+        let words = parse_wordlist("окно, Берег; ЗАМОК река-лес поле дом книга берег");
+        assert_eq!(words.len(), 8);
+        let ciphertext = VigenerProgressive::new("замок").unwrap().encrypt(PLAINTEXT);
+
+        let hits = dictionary_attack(
+            &ciphertext,
+            &words,
+            Alphabet::default(),
+            &model(Language::Russian),
+            3,
+            2,
+            |_| false,
+        );
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].key, "ЗАМОК");
+        assert_eq!(
+            hits[0].plaintext,
+            filter_russian(PLAINTEXT.chars()).collect::<String>()
+        );
+    }
+
+    #[test]
+    fn thread_count_does_not_change_result() {
+        let words = parse_wordlist(include_str!("../corpus/ru.txt"));
+        let ciphertext = VigenerProgressive::new("поезд").unwrap().encrypt(PLAINTEXT);
+        let model = model(Language::Russian);
+
+        let keys = |threads| {
+            dictionary_attack(
+                &ciphertext,
+                &words,
+                Alphabet::default(),
+                &model,
+                5,
+                threads,
+                |_| false,
+            )
+            .into_iter()
+            .map(|hit| hit.key)
+            .collect::<Vec<_>>()
+        };
+        let single = keys(1);
+        assert_eq!(single[0], "ПОЕЗД");
+        assert_eq!(single, keys(4));
+    }
+}
@@ -1,110 +1,250 @@
+use crate::error::CipherError;
+
 pub struct VigenerProgressive {
+    /* Номера букв ключа в обычном алфавите */
     key: Vec<u32>,
+    alphabet: Alphabet,
+}
+
+fn uppercase(c: char) -> char {
+    c.to_uppercase().next().unwrap_or(c)
 }
 
 pub fn filter_russian<I: Iterator<Item = char>>(inp: I) -> impl Iterator<Item = char> {
-    inp.filter_map(|c| {
-        let c = c.to_uppercase().next().unwrap();
-        if ('А'..='Я').contains(&c) || ('Ё' == c) {
-            Some(c)
-        } else {
-            None
-        }
-    })
+    inp.map(uppercase).filter(|&c| as_ru_u32(c).is_ok())
 }
 
-const ALPHABET: [char; 33] = [
+pub const ALPHABET: [char; 33] = [
     'А', 'Б', 'В', 'Г', 'Д', 'Е', 'Ё', 'Ж', 'З', 'И', 'Й', 'К', 'Л', 'М', 'Н', 'О', 'П', 'Р', 'С',
     'Т', 'У', 'Ф', 'Х', 'Ц', 'Ч', 'Ш', 'Щ', 'Ъ', 'Ы', 'Ь', 'Э', 'Ю', 'Я',
 ];
 
-fn rot(val: char, num: u32) -> char {
-    from_ru_u32((as_ru_u32(val) + num) % ALPHABET.len() as u32)
-}
-
-fn as_ru_u32(val: char) -> u32 {
+pub fn as_ru_u32(val: char) -> Result<u32, CipherError> {
     if ('А'..='Е').contains(&val) {
-        val as u32 - 'А' as u32
+        Ok(val as u32 - 'А' as u32)
     } else if val == 'Ё' {
-        as_ru_u32('Е') + 1
+        Ok(6)
     } else if ('Ж'..='Я').contains(&val) {
-        val as u32 - 'А' as u32 + 1
+        Ok(val as u32 - 'А' as u32 + 1)
     } else {
-        unreachable!()
+        Err(CipherError::InvalidCharacter(val))
     }
 }
 
-fn from_ru_u32(value: u32) -> char {
-    ALPHABET[value as usize]
+/* Номера русских букв текста в обычном алфавите, прочие символы пропускаются */
+fn russian_indices(text: &str) -> impl Iterator<Item = u32> + '_ {
+    text.chars().filter_map(|c| as_ru_u32(uppercase(c)).ok())
+}
+
+/* Столько разных букв в ключевом слове почти не бывает: такой алфавит таблицы —
+ * попытка записать перестановку, и ошибку в ней нужно показать, а не скрыть */
+const PERMUTATION_LETTERS: usize = 30;
+
+/* Порядок букв в строках таблицы шифрования: обычный, смешанный по ключевому слову
+ * или произвольная перестановка всех 33 букв */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Alphabet {
+    letters: [char; 33],
+    /* positions[as_ru_u32(c)] — номер буквы c в этом алфавите */
+    positions: [u32; 33],
+}
+
+impl Default for Alphabet {
+    fn default() -> Self {
+        Self::from_letters(ALPHABET)
+    }
+}
+
+impl Alphabet {
+    /* letters — русские буквы, иначе у пропущенных останется позиция 0 */
+    fn from_letters(letters: [char; 33]) -> Self {
+        let mut positions = [0; 33];
+        for (i, &c) in letters.iter().enumerate() {
+            if let Ok(index) = as_ru_u32(c) {
+                positions[index as usize] = i as u32;
+            }
+        }
+        Alphabet { letters, positions }
+    }
+
+    /* Сначала буквы ключевого слова без повторов, затем остальные по порядку */
+    pub fn from_keyword(keyword: &str) -> Self {
+        let mut letters = ALPHABET;
+        let mut count = 0;
+        for c in filter_russian(keyword.chars()).chain(ALPHABET) {
+            if !letters[..count].contains(&c) {
+                letters[count] = c;
+                count += 1;
+            }
+        }
+        Self::from_letters(letters)
+    }
+
+    /* Перестановка должна содержать каждую из 33 букв ровно один раз */
+    pub fn from_permutation(text: &str) -> Result<Self, CipherError> {
+        let letters: Vec<char> = filter_russian(text.chars()).collect();
+        let letters: [char; 33] = letters
+            .try_into()
+            .map_err(|_| CipherError::InvalidAlphabet)?;
+        if ALPHABET.iter().all(|c| letters.contains(c)) {
+            Ok(Self::from_letters(letters))
+        } else {
+            Err(CipherError::InvalidAlphabet)
+        }
+    }
+
+    /* Второй ключ вкладки: пустой — обычный алфавит, почти все буквы — перестановка,
+     * которая должна быть полной, иначе ключевое слово */
+    pub fn parse(key: &str) -> Result<Self, CipherError> {
+        let mut letters: Vec<char> = filter_russian(key.chars()).collect();
+        letters.sort_unstable();
+        letters.dedup();
+        if letters.len() >= PERMUTATION_LETTERS {
+            Self::from_permutation(key)
+        } else {
+            Ok(Self::from_keyword(key))
+        }
+    }
+
+    pub fn index(&self, c: char) -> Result<u32, CipherError> {
+        Ok(self.positions[as_ru_u32(c)? as usize])
+    }
+
+    pub fn letter(&self, index: u32) -> char {
+        self.letters[(index % ALPHABET.len() as u32) as usize]
+    }
+
+    pub fn letters(&self) -> String {
+        self.letters.iter().collect()
+    }
+
+    /* Сдвиг буквы с номером standard в обычном алфавите */
+    fn rot(&self, standard: u32, num: u32) -> char {
+        self.letter(self.positions[standard as usize] + num)
+    }
 }
 
 impl VigenerProgressive {
-    pub fn new(key: &str) -> Option<Self> {
-        let key: Vec<_> = filter_russian(key.chars()).map(as_ru_u32).collect();
+    /* Небуквенные символы ключа пропускаются, буквы должны быть русскими */
+    pub fn new(key: &str) -> Result<Self, CipherError> {
+        let key = key
+            .chars()
+            .filter(|c| c.is_alphabetic())
+            .map(|c| as_ru_u32(uppercase(c)).map_err(|_| CipherError::InvalidCharacter(c)))
+            .collect::<Result<Vec<_>, _>>()?;
 
         if key.is_empty() {
-            return None;
+            return Err(CipherError::EmptyKey);
         }
 
-        Some(VigenerProgressive { key })
+        Ok(VigenerProgressive {
+            key,
+            alphabet: Alphabet::default(),
+        })
+    }
+
+    /* Строки таблицы — сдвиги смешанного алфавита, буквы ключа нумеруются в нём же */
+    pub fn with_alphabet(self, alphabet: Alphabet) -> Self {
+        VigenerProgressive { alphabet, ..self }
+    }
+
+    fn shift(&self, key_num: &u32) -> u32 {
+        self.alphabet.positions[*key_num as usize]
     }
 
     pub fn encrypt(&self, text: &str) -> String {
-        let input = filter_russian(text.chars());
-
-        let mut text = String::new();
-        let mut cycle_num: u32 = 0;
-        let mut key_iter = self.key.iter();
-
-        for char in input {
-            let num = match key_iter.next() {
-                Some(num) => num,
-                None => {
-                    cycle_num += 1;
-                    key_iter = self.key.iter();
-                    key_iter.next().unwrap()
-                }
-            };
-            let key_num = num + cycle_num;
-            text.push(rot(char, key_num));
-        }
-        text
+        let len = ALPHABET.len() as u32;
+        russian_indices(text)
+            .zip(self.key.iter().cycle())
+            .enumerate()
+            .map(|(i, (char, key_num))| {
+                let cycle_num = (i / self.key.len()) as u32;
+                self.alphabet
+                    .rot(char, self.shift(key_num) + cycle_num % len)
+            })
+            .collect()
     }
 
     pub fn decrypt(&self, text: &str) -> String {
-        let input = filter_russian(text.chars());
-
-        let mut text = String::new();
-        let mut cycle_num: u32 = 0;
-        let mut key_iter = self.key.iter();
-
-        for char in input {
-            let key_num = match key_iter.next() {
-                Some(num) => num,
-                None => {
-                    cycle_num += 1;
-                    key_iter = self.key.iter();
-                    key_iter.next().unwrap()
-                }
-            };
-            text.push(rot(
-                char,
-                ALPHABET.len() as u32 - (key_num + cycle_num) % ALPHABET.len() as u32,
-            ));
-        }
-        text
+        let len = ALPHABET.len() as u32;
+        russian_indices(text)
+            .zip(self.key.iter().cycle())
+            .enumerate()
+            .map(|(i, (char, key_num))| {
+                let cycle_num = (i / self.key.len()) as u32;
+                self.alphabet
+                    .rot(char, len - (self.shift(key_num) + cycle_num) % len)
+            })
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn keyword_alphabet() {
+        let alphabet = Alphabet::from_keyword("шифровка");
+        assert!(alphabet
+            .letters()
+            .starts_with("ШИФРОВКАБГДЕЁЖЗЙЛМНПСТУХЦЧЩЪЫЬЭЮЯ"));
+        assert_eq!(Alphabet::parse(&alphabet.letters()).unwrap(), alphabet);
+        assert_eq!(Alphabet::parse("").unwrap(), Alphabet::default());
+        assert!(Alphabet::from_permutation("АБВ").is_err());
+    }
+
+    #[test]
+    fn broken_permutation_is_an_error() {
+        let letters = Alphabet::from_keyword("шифровка").letters();
+        /* Опечатка: буква повторена вместо другой */
+        let typo = letters.replacen('Я', "Ю", 1);
+        assert!(matches!(
+            Alphabet::parse(&typo),
+            Err(CipherError::InvalidAlphabet)
+        ));
+        let short: String = letters.chars().skip(1).collect();
+        assert!(matches!(
+            Alphabet::parse(&short),
+            Err(CipherError::InvalidAlphabet)
+        ));
+        /* Длинное ключевое слово с повторами остаётся ключевым словом */
+        assert_eq!(
+            Alphabet::parse("съешь же ещё этих мягких булок").unwrap(),
+            Alphabet::from_keyword("съешь же ещё этих мягких булок")
+        );
+    }
+
+    #[test]
+    fn standard_alphabet_keeps_old_cipher() {
+        let vig = VigenerProgressive::new("КЛЮЧ").unwrap();
+        assert_eq!(vig.encrypt("АААААААА"), "КЛЮЧЛМЯШ");
+        let mixed = VigenerProgressive::new("КЛЮЧ")
+            .unwrap()
+            .with_alphabet(Alphabet::from_keyword("ЗАМОК"));
+        assert_ne!(mixed.encrypt("АААААААА"), "КЛЮЧЛМЯШ");
+    }
+
+    #[test]
+    fn key_errors() {
+        assert!(matches!(
+            VigenerProgressive::new(" ,.-"),
+            Err(CipherError::EmptyKey)
+        ));
+        assert!(matches!(
+            VigenerProgressive::new("ключ key"),
+            Err(CipherError::InvalidCharacter('k'))
+        ));
+        assert!(VigenerProgressive::new("к-л-ю-ч 2").is_ok());
+        assert!(Alphabet::default().index('Q').is_err());
+    }
+
     use proptest::prelude::*;
     proptest! {
         #[test]
         fn enc_dec_works(s in "\\PC*", k in "\\PC*") {
             let vig = VigenerProgressive::new(&k);
-                if let Some(vig) = vig {
+            if let Ok(vig) = vig {
                 let enc = vig.encrypt(&s);
                 let dec = vig.decrypt(&enc);
                 let enc2 = vig.encrypt(&dec);
@@ -113,6 +253,15 @@ mod tests {
             }
         }
 
+        #[test]
+        fn mixed_alphabet_round_trip(s in "\\p{Cyrillic}*", k in "\\p{Cyrillic}+", a in "\\p{Cyrillic}*") {
+            if let Ok(vig) = VigenerProgressive::new(&k) {
+                let vig = vig.with_alphabet(Alphabet::parse(&a).unwrap_or_default());
+                let plain: String = filter_russian(s.chars()).collect();
+                prop_assert_eq!(vig.decrypt(&vig.encrypt(&s)), plain);
+            }
+        }
+
         /*
         #[test]
         fn enc_dec_works_ru(s in "\\p{Cyrillic}*", k in "\\p{Cyrillic}+") {